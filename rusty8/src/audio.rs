@@ -0,0 +1,153 @@
+// Sound output through cpal, shared by the window and terminal frontends.
+//
+// The interpreter reports what to play through the sound hooks, the frontend
+// passes it on to Audio and the audio thread renders the current MegaChip
+// sample (8-bit unsigned PCM at its own rate) resampled to the device rate.
+
+use crate::hooks::Sound;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::sync::{Arc, Mutex};
+
+const VOLUME: f32 = 0.2;
+
+enum Voice {
+    Silent,
+    Sample {
+        data: Vec<u8>,
+        step: f32, // sample positions per output frame
+        position: f32,
+        looping: bool,
+    },
+}
+
+pub struct Audio {
+    voice: Arc<Mutex<Voice>>,
+    sample_rate: f32,
+    _stream: Stream, // sound stops when dropped
+}
+
+impl Audio {
+    // None without a usable output device, emulation then runs silently
+    pub fn new() -> Option<Self> {
+        let device = cpal::default_host().default_output_device()?;
+        let config = device.default_output_config().ok()?;
+        let sample_rate = config.sample_rate() as f32;
+        let voice = Arc::new(Mutex::new(Voice::Silent));
+
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, config.into(), voice.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, config.into(), voice.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, config.into(), voice.clone()),
+            SampleFormat::I32 => build_stream::<i32>(&device, config.into(), voice.clone()),
+            _ => None,
+        }?;
+        stream.play().ok()?;
+
+        Some(Audio {
+            voice,
+            sample_rate,
+            _stream: stream,
+        })
+    }
+
+    // replaces whatever is playing
+    pub fn play(&self, sound: Sound) {
+        let voice = Voice::Sample {
+            data: sound.data.to_vec(),
+            step: sound.rate as f32 / self.sample_rate,
+            position: 0.0,
+            looping: sound.looping,
+        };
+
+        *self.voice.lock().unwrap() = voice;
+    }
+
+    pub fn stop(&self) {
+        *self.voice.lock().unwrap() = Voice::Silent;
+    }
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: StreamConfig,
+    voice: Arc<Mutex<Voice>>,
+) -> Option<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+
+    device
+        .build_output_stream(
+            config,
+            move |output: &mut [T], _| {
+                let mut voice = voice.lock().unwrap();
+                for frame in output.chunks_mut(channels) {
+                    let value = T::from_sample(next_value(&mut voice) * VOLUME);
+                    frame.fill(value);
+                }
+            },
+            |err| eprintln!("Audio: {err}"),
+            None,
+        )
+        .ok()
+}
+
+// the next output value in -1..1, advances the voice by one frame
+fn next_value(voice: &mut Voice) -> f32 {
+    match voice {
+        Voice::Silent => 0.0,
+        Voice::Sample {
+            data,
+            step,
+            position,
+            looping,
+        } => {
+            if *position as usize >= data.len() {
+                if !*looping || data.is_empty() {
+                    *voice = Voice::Silent;
+                    return 0.0;
+                }
+                *position %= data.len() as f32;
+            }
+
+            let value = (data[*position as usize] as f32 - 128.0) / 128.0;
+            *position += *step;
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_is_resampled_and_ends() {
+        let mut voice = Voice::Sample {
+            data: vec![128, 255, 0],
+            step: 0.5,
+            position: 0.0,
+            looping: false,
+        };
+        let values: Vec<f32> = (0..8).map(|_| next_value(&mut voice)).collect();
+        assert_eq!(
+            values,
+            [0.0, 0.0, 127.0 / 128.0, 127.0 / 128.0, -1.0, -1.0, 0.0, 0.0]
+        );
+        assert!(matches!(voice, Voice::Silent));
+    }
+
+    #[test]
+    fn looping_sample_starts_over() {
+        let mut voice = Voice::Sample {
+            data: vec![0, 255],
+            step: 1.0,
+            position: 0.0,
+            looping: true,
+        };
+        let values: Vec<f32> = (0..4).map(|_| next_value(&mut voice)).collect();
+        assert_eq!(values, [-1.0, 127.0 / 128.0, -1.0, 127.0 / 128.0]);
+    }
+}
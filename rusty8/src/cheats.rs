@@ -0,0 +1,301 @@
+// Game Genie style memory patches, loaded per ROM.
+//
+// A cheat file has one cheat per line, `#` starts a comment:
+//
+//     rom   3a1c0f...           # optional, SHA-1 of the ROM the file belongs to
+//     once   0x2F0 0x05 Level 5  # written once when the ROM is loaded
+//     freeze 0x2F1 0x03 Lives    # written again before every frame
+//
+// Files are looked up next to the ROM (`breakout.cht`) and in the
+// `cheats` folder of the config directory (`<sha1>.cht`).
+
+use crate::paths::config_dir;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn rom_hash(rom_data: &[u8]) -> String {
+    Sha1::digest(rom_data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CheatKind {
+    Once,
+    Freeze,
+}
+
+struct Cheat {
+    kind: CheatKind,
+    address: usize,
+    value: u8,
+    original: u8,
+    name: String,
+    enabled: bool,
+}
+
+pub struct CheatList {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    fn empty() -> Self {
+        CheatList { cheats: Vec::new() }
+    }
+
+    pub fn load(rom_path: &str, rom_hash: &str) -> Self {
+        let candidates: Vec<PathBuf> = std::iter::once(Path::new(rom_path).with_extension("cht"))
+            .chain(config_dir().map(|dir| dir.join("cheats").join(format!("{rom_hash}.cht"))))
+            .collect();
+
+        for path in candidates {
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+
+            match Self::parse(&text, rom_hash) {
+                Ok(list) => {
+                    println!(
+                        "Loaded {} cheat(s) from {}",
+                        list.cheats.len(),
+                        path.display()
+                    );
+                    return list;
+                }
+                Err(err) => eprintln!("Ignoring cheat file {}: {err}", path.display()),
+            }
+        }
+
+        Self::empty()
+    }
+
+    fn parse(text: &str, rom_hash: &str) -> Result<Self, String> {
+        let mut cheats = Vec::new();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let keyword = fields.next().unwrap_or("");
+
+            if keyword == "rom" {
+                let expected = fields.next().unwrap_or("").to_ascii_lowercase();
+                if expected != rom_hash {
+                    return Err(format!(
+                        "written for ROM {expected}, loaded ROM is {rom_hash}"
+                    ));
+                }
+                continue;
+            }
+
+            let kind = match keyword {
+                "once" => CheatKind::Once,
+                "freeze" => CheatKind::Freeze,
+                _ => {
+                    return Err(format!(
+                        "line {}: unknown cheat type '{keyword}'",
+                        line_no + 1
+                    ));
+                }
+            };
+
+            let address = fields.next().and_then(parse_number);
+            let value = fields.next().and_then(parse_number);
+            let (Some(address), Some(value)) = (address, value) else {
+                return Err(format!("line {}: expected <address> <value>", line_no + 1));
+            };
+
+            // the address is checked against the memory of the variant in apply_on_load
+            if value > 0xFF {
+                return Err(format!("line {}: value out of range", line_no + 1));
+            }
+
+            let name = fields.collect::<Vec<_>>().join(" ");
+
+            cheats.push(Cheat {
+                kind,
+                address,
+                value: value as u8,
+                original: 0,
+                name: if name.is_empty() {
+                    format!("{address:#05X}")
+                } else {
+                    name
+                },
+                enabled: true,
+            });
+        }
+
+        Ok(CheatList { cheats })
+    }
+
+    // called once after the ROM is in memory, remembers the bytes we overwrite;
+    // cheats past the end of memory are dropped
+    pub fn apply_on_load(&mut self, memory: &mut [u8]) {
+        self.cheats.retain(|cheat| {
+            let fits = cheat.address < memory.len();
+            if !fits {
+                eprintln!(
+                    "Ignoring cheat {}: address {:#05X} is past the end of memory",
+                    cheat.name, cheat.address
+                );
+            }
+            fits
+        });
+
+        for (idx, cheat) in self.cheats.iter_mut().enumerate() {
+            cheat.original = memory[cheat.address];
+            memory[cheat.address] = cheat.value;
+            println!("Cheat F{}: {} [on]", idx + 1, cheat.name);
+        }
+    }
+
+    pub fn apply_frozen(&self, memory: &mut [u8]) {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled && cheat.kind == CheatKind::Freeze)
+            .for_each(|cheat| memory[cheat.address] = cheat.value);
+    }
+
+    // the active patches, for comparing setups in the netplay handshake
+    pub fn describe(&self) -> String {
+        let active: Vec<String> = self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .map(|cheat| match cheat.kind {
+                CheatKind::Once => format!("once {:#05X}={:#04X}", cheat.address, cheat.value),
+                CheatKind::Freeze => format!("freeze {:#05X}={:#04X}", cheat.address, cheat.value),
+            })
+            .collect();
+
+        if active.is_empty() {
+            "none".to_string()
+        } else {
+            active.join(" ")
+        }
+    }
+
+    // disabling a one-time patch restores the original byte, enabling writes it again,
+    // returns the new state for the frontend's status line
+    pub fn toggle(&mut self, idx: usize, memory: &mut [u8]) -> Option<String> {
+        let cheat = self.cheats.get_mut(idx)?;
+
+        cheat.enabled = !cheat.enabled;
+
+        if cheat.kind == CheatKind::Once {
+            memory[cheat.address] = if cheat.enabled {
+                cheat.value
+            } else {
+                cheat.original
+            };
+        }
+
+        Some(format!(
+            "Cheat F{}: {} [{}]",
+            idx + 1,
+            cheat.name,
+            if cheat.enabled { "on" } else { "off" }
+        ))
+    }
+}
+
+// decimal or 0x-prefixed hex
+pub fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "3a1c0f5e2b4d6a7c8e9f0a1b2c3d4e5f6a7b8c9d";
+
+    #[test]
+    fn parses_once_and_freeze_cheats() {
+        let text = "# comment\nonce 0x2F0 5 Start at level 5\n\nfreeze 752 0x03 # lives\n";
+        let list = CheatList::parse(text, HASH).unwrap();
+
+        assert_eq!(list.cheats.len(), 2);
+        assert!(list.cheats[0].kind == CheatKind::Once);
+        assert_eq!(list.cheats[0].address, 0x2F0);
+        assert_eq!(list.cheats[0].value, 5);
+        assert_eq!(list.cheats[0].name, "Start at level 5");
+        assert!(list.cheats[1].kind == CheatKind::Freeze);
+        assert_eq!(list.cheats[1].address, 0x2F0);
+        assert_eq!(list.cheats[1].name, "0x2F0");
+    }
+
+    #[test]
+    fn checks_the_rom_hash() {
+        let text = format!("rom {}\nonce 0x200 1", HASH.to_ascii_uppercase());
+        assert!(CheatList::parse(&text, HASH).is_ok());
+
+        let text = "rom 0000000000000000000000000000000000000000\nonce 0x200 1";
+        let Err(err) = CheatList::parse(text, HASH) else {
+            panic!("accepted a cheat file for another ROM");
+        };
+        assert!(err.contains("written for ROM"));
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(CheatList::parse("poke 0x200 1", HASH).is_err());
+        assert!(CheatList::parse("once 0x200", HASH).is_err());
+        assert!(CheatList::parse("once zz 1", HASH).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        assert!(CheatList::parse("freeze 0x200 0xFF", HASH).is_ok());
+        assert!(CheatList::parse("freeze 0x200 0x100", HASH).is_err());
+    }
+
+    #[test]
+    fn drops_cheats_past_the_end_of_memory() {
+        let text = "once 0xFFF 1
+once 0x1000 2 MegaChip only";
+
+        let mut list = CheatList::parse(text, HASH).unwrap();
+        let mut memory = vec![0; crate::MEMORY_SIZE];
+        list.apply_on_load(&mut memory);
+        assert_eq!(list.cheats.len(), 1);
+        assert_eq!(memory[0xFFF], 1);
+
+        let mut list = CheatList::parse(text, HASH).unwrap();
+        let mut memory = vec![0; crate::megachip::MEGA_MEMORY_SIZE];
+        list.apply_on_load(&mut memory);
+        assert_eq!(list.cheats.len(), 2);
+        assert_eq!(memory[0x1000], 2);
+    }
+
+    #[test]
+    fn toggling_a_once_cheat_restores_the_original_byte() {
+        let mut list = CheatList::parse("once 0x200 0x42", HASH).unwrap();
+        let mut memory = vec![0x11; crate::MEMORY_SIZE];
+
+        list.apply_on_load(&mut memory);
+        assert_eq!(memory[0x200], 0x42);
+        list.toggle(0, &mut memory);
+        assert_eq!(memory[0x200], 0x11);
+        list.toggle(0, &mut memory);
+        assert_eq!(memory[0x200], 0x42);
+    }
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number("0x2F0"), Some(0x2F0));
+        assert_eq!(parse_number("0X2f0"), Some(0x2F0));
+        assert_eq!(parse_number("752"), Some(752));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("-1"), None);
+    }
+}
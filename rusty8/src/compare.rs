@@ -0,0 +1,202 @@
+// --compare runs the ROM on two interpreters in lockstep with the same input and
+// RNG seed, the second one with another variant or other quirks, and shows both
+// screens side by side. It stops after the first instruction that leaves the two
+// machines in different states, which is exactly where the configurations
+// disagree about the program, and reports everything that differs.
+
+use crate::diff::diff;
+use crate::flags::FLAG_COUNT;
+use crate::frontend::Frontend;
+use crate::hooks::{Display, NoHooks};
+use crate::quirks::Variant;
+use crate::romdb::Profile;
+use crate::{Chip8, FPS_TARGET, Limits, SCREEN_HEIGHT, SCREEN_WIDTH};
+use rand::prelude::*;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const LEFT_COLOR: u32 = 0xFFA500;
+const RIGHT_COLOR: u32 = 0x00A5FF;
+const DIFF_COLOR: u32 = 0xFF0000; // lit on one side only
+
+// the spec is a list like "schip" or "-vf_reset,+jump_uses_vx", a variant
+// resets the quirks to its defaults, +name/-name adjust them afterwards
+pub fn other_profile(profile: &Profile, spec: &str) -> Result<Profile, String> {
+    let mut other = profile.clone();
+
+    for token in spec.split([',', ' ']).filter(|token| !token.is_empty()) {
+        if let Some(variant) = Variant::from_name(token) {
+            other.set_variant(variant);
+            continue;
+        }
+
+        other
+            .quirks
+            .apply(token)
+            .map_err(|_| format!("unknown variant or quirk '{token}'"))?;
+    }
+
+    Ok(other)
+}
+
+pub fn describe(profile: &Profile) -> String {
+    let quirks = profile.quirks.enabled();
+    if quirks.is_empty() {
+        format!("{}, no quirks", profile.variant.name())
+    } else {
+        format!("{}, {}", profile.variant.name(), quirks.join(" "))
+    }
+}
+
+// runs until the frontend is closed, returns the report if the machines diverged;
+// with report_now it also goes to stderr right away, e.g. next to a window
+pub fn run_compare(
+    program: &[u8],
+    profile: &Profile,
+    other: &Profile,
+    limits: &Limits,
+    report_now: bool,
+    frontend: &mut dyn Frontend,
+) -> Option<String> {
+    let mut left = Chip8::new(program, profile, [0; FLAG_COUNT], limits);
+    let mut right = Chip8::new(program, other, [0; FLAG_COUNT], limits);
+    let seed = rand::random();
+    left.rng = StdRng::seed_from_u64(seed);
+    right.rng = StdRng::seed_from_u64(seed);
+
+    let frame_time_target = Duration::from_secs_f64(1.0 / FPS_TARGET as f64);
+    let mut composite = vec![0; SCREEN_WIDTH * 2 * SCREEN_HEIGHT];
+    let mut steps = 0;
+    let mut report = None;
+
+    frontend.set_status(&format!(
+        "Rusty8 | left: {} | right: {}",
+        describe(profile),
+        describe(other)
+    ));
+
+    while frontend.is_open() {
+        let start_time = Instant::now();
+
+        // keeps the keypad overlay and the window responsive after a halt
+        left.handle_input(frontend);
+
+        if report.is_none() {
+            right.prev_keys = left.prev_keys;
+            right.keys = left.keys;
+            left.update_timers();
+            right.update_timers();
+
+            // both cores run at the speed of the first configuration
+            for _ in 0..profile.instr_per_frame {
+                let pc = left.pc;
+                let opcode = left
+                    .memory
+                    .get(pc..pc + 2)
+                    .map_or(0, |op| u16::from_be_bytes([op[0], op[1]]));
+
+                let results = (left.step(frontend), right.step(&mut NoHooks));
+                steps += 1;
+
+                let mut lines = match &results {
+                    (Err(fault), Err(other_fault)) if fault == other_fault => {
+                        report = Some(format!("Both stopped at {fault}"));
+                        break;
+                    }
+                    (Ok(()), Ok(())) => Vec::new(),
+                    (left_result, right_result) => {
+                        let describe = |result: &Result<(), _>| match result {
+                            Ok(()) => "ok".to_string(),
+                            Err(fault) => format!("halted at {fault}"),
+                        };
+                        vec![format!(
+                            "left {}, right {}",
+                            describe(left_result),
+                            describe(right_result)
+                        )]
+                    }
+                };
+                lines.extend(diff(&left.state(), &right.state()));
+
+                if !lines.is_empty() {
+                    let text = format!(
+                        "Diverged after {steps} instructions, {opcode:#06X} at {pc:#05X} \
+                         (left vs right):\n  {}",
+                        lines.join("\n  ")
+                    );
+                    if report_now {
+                        eprintln!("Left:  {}", describe(profile));
+                        eprintln!("Right: {}", describe(other));
+                        eprintln!("{text}");
+                        frontend.set_status(&format!(
+                            "Rusty8 | diverged at {pc:#05X} | differences printed"
+                        ));
+                    } else {
+                        frontend.set_status(&format!(
+                            "Rusty8 | diverged at {pc:#05X} | close to see the differences"
+                        ));
+                    }
+                    report = Some(text);
+                    break;
+                }
+            }
+        }
+
+        for (i, pixel) in composite.iter_mut().enumerate() {
+            let (x, y) = (i % (SCREEN_WIDTH * 2), i / (SCREEN_WIDTH * 2));
+            let idx = y * SCREEN_WIDTH + x % SCREEN_WIDTH;
+            let (own, theirs, color) = if x < SCREEN_WIDTH {
+                (left.gfx[idx], right.gfx[idx], LEFT_COLOR)
+            } else {
+                (right.gfx[idx], left.gfx[idx], RIGHT_COLOR)
+            };
+            *pixel = match (own, theirs) {
+                (0, _) => 0x000000,
+                (_, 0) => DIFF_COLOR,
+                _ => color,
+            };
+        }
+        let display = Display::Color {
+            pixels: &composite,
+            width: SCREEN_WIDTH * 2,
+            height: SCREEN_HEIGHT,
+        };
+        frontend.on_draw(display, &left.keys);
+
+        sleep(frame_time_target.saturating_sub(start_time.elapsed()));
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn variant_preset_then_quirk_changes() {
+        let other = other_profile(&Profile::default(), "schip,-jump_uses_vx").unwrap();
+
+        assert!(other.variant == Variant::SuperChip);
+        assert!(
+            other.quirks
+                == Quirks {
+                    jump_uses_vx: false,
+                    ..Variant::SuperChip.default_quirks()
+                }
+        );
+        assert_eq!(
+            other.instr_per_frame,
+            Variant::SuperChip.default_instr_per_frame()
+        );
+    }
+
+    #[test]
+    fn unknown_tokens_are_errors() {
+        let Err(err) = other_profile(&Profile::default(), "-vf_reset bogus") else {
+            panic!("accepted an unknown token");
+        };
+        assert_eq!(err, "unknown variant or quirk 'bogus'");
+    }
+}
@@ -0,0 +1,146 @@
+// Field-by-field comparison of two machines, used by --verify (interpreter vs
+// reference.rs) and --compare (two interpreters with different quirks).
+
+use crate::SCREEN_WIDTH;
+
+// listing more differing bytes or pixels than this doesn't help anyone
+const MAX_LISTED: usize = 8;
+
+// a read-only view of everything that makes up the machine state
+pub struct State<'a> {
+    pub pc: usize,
+    pub i: usize,
+    pub v: &'a [u8; 16],
+    pub stack: &'a [usize],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub flags: &'a [u8],
+    pub memory: &'a [u8],
+    pub gfx: &'a [u8], // 64x32, one byte per pixel
+}
+
+// one line per difference, values of a first, empty if the states are equal
+pub fn diff(a: &State, b: &State) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if a.pc != b.pc {
+        lines.push(format!("PC {:#05X} vs {:#05X}", a.pc, b.pc));
+    }
+    if a.i != b.i {
+        lines.push(format!("I {:#05X} vs {:#05X}", a.i, b.i));
+    }
+    for reg in (0..16).filter(|&reg| a.v[reg] != b.v[reg]) {
+        lines.push(format!("V{reg:X} {:#04X} vs {:#04X}", a.v[reg], b.v[reg]));
+    }
+    if a.stack != b.stack {
+        lines.push(format!("stack {:03X?} vs {:03X?}", a.stack, b.stack));
+    }
+    if a.delay_timer != b.delay_timer {
+        lines.push(format!(
+            "delay timer {} vs {}",
+            a.delay_timer, b.delay_timer
+        ));
+    }
+    if a.sound_timer != b.sound_timer {
+        lines.push(format!(
+            "sound timer {} vs {}",
+            a.sound_timer, b.sound_timer
+        ));
+    }
+    if a.flags != b.flags {
+        lines.push(format!("user flags {:02X?} vs {:02X?}", a.flags, b.flags));
+    }
+
+    if a.memory.len() != b.memory.len() {
+        lines.push(format!(
+            "memory size {} vs {} bytes",
+            a.memory.len(),
+            b.memory.len()
+        ));
+    }
+    let addresses = (0..a.memory.len().min(b.memory.len()))
+        .filter(|&address| a.memory[address] != b.memory[address]);
+    lines.extend(listed(addresses, |address| {
+        format!(
+            "memory at {address:#05X}: {:#04X} vs {:#04X}",
+            a.memory[address], b.memory[address]
+        )
+    }));
+
+    let pixels = (0..a.gfx.len()).filter(|&pixel| a.gfx[pixel] != b.gfx[pixel]);
+    lines.extend(listed(pixels, |pixel| {
+        format!(
+            "pixel ({}, {}): {} vs {}",
+            pixel % SCREEN_WIDTH,
+            pixel / SCREEN_WIDTH,
+            a.gfx[pixel],
+            b.gfx[pixel]
+        )
+    }));
+
+    lines
+}
+
+// the first MAX_LISTED items, then how many were left out
+fn listed(items: impl Iterator<Item = usize>, describe: impl Fn(usize) -> String) -> Vec<String> {
+    let items: Vec<usize> = items.collect();
+    let mut lines: Vec<String> = items
+        .iter()
+        .take(MAX_LISTED)
+        .map(|&item| describe(item))
+        .collect();
+    if items.len() > MAX_LISTED {
+        lines.push(format!("... {} more", items.len() - MAX_LISTED));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state<'a>(v: &'a [u8; 16], memory: &'a [u8], gfx: &'a [u8]) -> State<'a> {
+        State {
+            pc: 0x200,
+            i: 0,
+            v,
+            stack: &[],
+            delay_timer: 0,
+            sound_timer: 0,
+            flags: &[],
+            memory,
+            gfx,
+        }
+    }
+
+    #[test]
+    fn equal_states_have_no_differences() {
+        let memory = [0; 16];
+        let gfx = [0; 4];
+        let a = state(&[0; 16], &memory, &gfx);
+        assert!(diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn lists_at_most_max_listed_differences() {
+        let v = [0; 16];
+        let mut other_v = [0; 16];
+        other_v[0xF] = 1;
+        let memory = [0; 16];
+        let other_memory = [0xFF; 16];
+        let gfx = [0; 4];
+        let other_gfx = [0, 1, 0, 0];
+
+        let lines = diff(
+            &state(&v, &memory, &gfx),
+            &state(&other_v, &other_memory, &other_gfx),
+        );
+
+        assert_eq!(lines.len(), 1 + MAX_LISTED + 1 + 1);
+        assert_eq!(lines[0], "VF 0x00 vs 0x01");
+        assert_eq!(lines[1], "memory at 0x000: 0x00 vs 0xFF");
+        assert_eq!(lines[MAX_LISTED], "memory at 0x007: 0x00 vs 0xFF");
+        assert_eq!(lines[MAX_LISTED + 1], "... 8 more");
+        assert_eq!(lines[MAX_LISTED + 2], "pixel (1, 0): 0 vs 1");
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum FaultKind {
+    StackOverflow(usize),
+    StackUnderflow,
+    ProtectedWrite(usize),
+    OutOfBounds(usize),
+}
+
+// an instruction the interpreter refused to execute, stops emulation
+#[derive(PartialEq)]
+pub struct Fault {
+    pub pc: usize,
+    pub opcode: u16,
+    pub kind: FaultKind,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PC {:#05X}, opcode {:#06X}: ", self.pc, self.opcode)?;
+
+        match self.kind {
+            FaultKind::StackOverflow(depth) => write!(f, "stack overflow, limit is {depth} levels"),
+            FaultKind::StackUnderflow => write!(f, "stack underflow, return without call"),
+            FaultKind::ProtectedWrite(address) => {
+                write!(f, "write to protected address {address:#05X}")
+            }
+            FaultKind::OutOfBounds(address) => {
+                write!(f, "memory access past the end at {address:#05X}")
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+// Persistent SCHIP user flags (FX75/FX85), the HP-48 RPL flags games use for
+// high scores. Stored per ROM as `flags/<sha1>.bin` in the config directory.
+
+use crate::paths::config_dir;
+use std::fs;
+use std::path::PathBuf;
+
+pub const FLAG_COUNT: usize = 16;
+
+fn flags_path(rom_hash: &str) -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("flags").join(format!("{rom_hash}.bin")))
+}
+
+pub fn load_flags(rom_hash: &str) -> [u8; FLAG_COUNT] {
+    let mut flags = [0u8; FLAG_COUNT];
+
+    if let Some(data) = flags_path(rom_hash).and_then(|path| fs::read(path).ok()) {
+        let len = data.len().min(FLAG_COUNT);
+        flags[..len].copy_from_slice(&data[..len]);
+    }
+
+    flags
+}
+
+pub fn save_flags(rom_hash: &str, flags: &[u8; FLAG_COUNT]) {
+    let Some(path) = flags_path(rom_hash) else {
+        eprintln!("No config directory, user flags are not saved");
+        return;
+    };
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, flags));
+
+    if let Err(err) = result {
+        eprintln!("Failed to save user flags to {}: {err}", path.display());
+    }
+}
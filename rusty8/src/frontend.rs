@@ -0,0 +1,17 @@
+// Everything the main loop needs from a display/input backend, drawing is done
+// through the interpreter hooks. Implemented by the minifb window (window.rs)
+// and the terminal (tui.rs).
+
+use crate::hooks::Hooks;
+
+pub trait Frontend: Hooks {
+    fn is_open(&self) -> bool;
+
+    // overwrites keys with the current state of the CHIP-8 keypad
+    fn read_keys(&mut self, keys: &mut [bool; 16]);
+
+    // F1-F12, pressed since the last read_keys
+    fn cheat_hotkey_pressed(&self, idx: usize) -> bool;
+
+    fn set_status(&mut self, status: &str);
+}
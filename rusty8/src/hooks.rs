@@ -0,0 +1,43 @@
+// Events the interpreter reports while it runs. Frontends and tools implement
+// the methods they care about instead of polling the interpreter state, the
+// built-in window and terminal frontends draw through on_draw as well.
+
+pub enum Display<'a> {
+    // 64x32 CHIP-8 display, one byte per pixel
+    Mono(&'a [u8]),
+    // MegaChip display, 0RGB pixels
+    Color {
+        pixels: &'a [u32],
+        width: usize,
+        height: usize,
+    },
+}
+
+// MegaChip digitized sound, 8-bit unsigned mono PCM
+#[cfg_attr(not(feature = "audio"), allow(dead_code))] // only read by the audio output
+pub struct Sound<'a> {
+    pub data: &'a [u8],
+    pub rate: usize, // samples per second
+    pub looping: bool,
+}
+
+#[allow(unused_variables)]
+pub trait Hooks {
+    // called once at the end of every frame, keys is the keypad state
+    fn on_draw(&mut self, display: Display, keys: &[bool; 16]) {}
+
+    // sound replaces whatever played before, stop is called when it ends
+    fn on_sound_start(&mut self, sound: Sound) {}
+    fn on_sound_stop(&mut self) {}
+
+    // pc is the address of the opcode, which is skipped
+    fn on_unknown_opcode(&mut self, pc: usize, opcode: u16) {}
+
+    // FX0A started waiting for a key to be released, register is X
+    fn on_key_wait(&mut self, register: usize) {}
+}
+
+// ignores every event, e.g. for --verify
+pub struct NoHooks;
+
+impl Hooks for NoHooks {}
@@ -0,0 +1,92 @@
+// physical keys, independent of the frontend that reads them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostKey {
+    Char(char), // lowercase letter or digit
+    Up,
+    Down,
+    Left,
+    Right,
+    Space,
+    Enter,
+}
+
+// physical key -> CHIP-8 key, several physical keys may share a CHIP-8 key
+pub type KeyBindings = Vec<(HostKey, usize)>;
+
+// 1 2 3 C      1 2 3 4
+// 4 5 6 D  <-  Q W E R
+// 7 8 9 E      A S D F
+// A 0 B F      Z X C V
+pub const DEFAULT_BINDINGS: [(HostKey, usize); 16] = [
+    (HostKey::Char('1'), 0x1),
+    (HostKey::Char('2'), 0x2),
+    (HostKey::Char('3'), 0x3),
+    (HostKey::Char('4'), 0xC),
+    (HostKey::Char('q'), 0x4),
+    (HostKey::Char('w'), 0x5),
+    (HostKey::Char('e'), 0x6),
+    (HostKey::Char('r'), 0xD),
+    (HostKey::Char('a'), 0x7),
+    (HostKey::Char('s'), 0x8),
+    (HostKey::Char('d'), 0x9),
+    (HostKey::Char('f'), 0xE),
+    (HostKey::Char('z'), 0xA),
+    (HostKey::Char('x'), 0x0),
+    (HostKey::Char('c'), 0xB),
+    (HostKey::Char('v'), 0xF),
+];
+
+// parses `left:7`, binding the physical key to the CHIP-8 key
+pub fn parse_binding(text: &str) -> Option<(HostKey, usize)> {
+    let (name, chip_key) = text.split_once(':')?;
+    let chip_key = usize::from_str_radix(chip_key, 16)
+        .ok()
+        .filter(|&k| k < 16)?;
+
+    Some((parse_key(name)?, chip_key))
+}
+
+fn parse_key(name: &str) -> Option<HostKey> {
+    let name = name.to_ascii_lowercase();
+
+    let key = match name.as_str() {
+        "up" => HostKey::Up,
+        "down" => HostKey::Down,
+        "left" => HostKey::Left,
+        "right" => HostKey::Right,
+        "space" => HostKey::Space,
+        "enter" => HostKey::Enter,
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii_alphanumeric() => HostKey::Char(c),
+                _ => return None,
+            }
+        }
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bindings() {
+        assert_eq!(parse_binding("left:7"), Some((HostKey::Left, 7)));
+        assert_eq!(parse_binding("Space:a"), Some((HostKey::Space, 0xA)));
+        assert_eq!(parse_binding("W:F"), Some((HostKey::Char('w'), 0xF)));
+        assert_eq!(parse_binding("5:0"), Some((HostKey::Char('5'), 0)));
+    }
+
+    #[test]
+    fn rejects_bad_bindings() {
+        assert_eq!(parse_binding("left"), None);
+        assert_eq!(parse_binding("left:10"), None);
+        assert_eq!(parse_binding("left:g"), None);
+        assert_eq!(parse_binding("pageup:1"), None);
+        assert_eq!(parse_binding("ab:1"), None);
+        assert_eq!(parse_binding(":1"), None);
+    }
+}
@@ -1,10 +1,61 @@
-use minifb::{Scale, Window, WindowOptions};
+#[cfg(feature = "audio")]
+mod audio;
+mod cheats;
+mod compare;
+mod diff;
+mod fault;
+mod flags;
+mod frontend;
+mod hooks;
+mod keymap;
+mod megachip;
+mod netplay;
+mod overlay;
+mod paths;
+mod quirks;
+mod reference;
+mod rom;
+mod romdb;
+mod tui;
+mod verify;
+mod window;
+
+// without the audio feature the frontends get no output device and stay silent
+#[cfg(not(feature = "audio"))]
+mod audio {
+    use crate::hooks::Sound;
+
+    pub struct Audio;
+
+    impl Audio {
+        pub fn new() -> Option<Self> {
+            None
+        }
+
+        pub fn play(&self, _sound: Sound) {}
+
+        pub fn stop(&self) {}
+    }
+}
+
+use cheats::{CheatList, parse_number, rom_hash};
+use diff::State;
+use fault::{Fault, FaultKind};
+use flags::{FLAG_COUNT, load_flags, save_flags};
+use frontend::Frontend;
+use hooks::{Display, Hooks, Sound};
+use megachip::{MEGA_HEIGHT, MEGA_WIDTH, MegaChip};
+use netplay::Netplay;
+use quirks::{Quirks, Variant};
 use rand::prelude::*;
 use raw_cpuid::CpuId;
+use romdb::{Profile, RomDb};
 use std::env;
 use std::fs;
 use std::thread::sleep;
 use std::time::Duration;
+use tui::TuiFrontend;
+use window::WindowFrontend;
 
 const INSTR_PER_FRAME: usize = 11;
 const FPS_TARGET: usize = 60;
@@ -13,11 +64,36 @@ const PROGRAM_START: usize = 0x200;
 const FONTSET_START: usize = 0x50;
 const SCREEN_WIDTH: usize = 64;
 const SCREEN_HEIGHT: usize = 32;
+const STACK_DEPTH: usize = 16;
+const MAX_STACK_DEPTH: usize = 256; // the stack is preallocated
+
+const FONTSET: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+struct Limits {
+    stack_depth: usize,
+    strict_memory: bool, // writes below the program or to the font fault
+}
 
 struct Chip8 {
-    memory: [u8; MEMORY_SIZE],
+    memory: Vec<u8>,
     gfx: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
-    screen_buffer: [u32; SCREEN_WIDTH * SCREEN_HEIGHT],
     v: [u8; 16],
     keys: [bool; 16],
     prev_keys: [bool; 16],
@@ -26,94 +102,102 @@ struct Chip8 {
     i: usize,
     delay_timer: u8,
     sound_timer: u8,
-    window: Window,
-    rng: ThreadRng,
+    rng: StdRng,
+    quirks: Quirks,
+    variant: Variant,
+    flags: [u8; FLAG_COUNT],
+    flags_dirty: bool,
+    stack_depth: usize,
+    strict_memory: bool,
+    sound_playing: bool, // the hooks were told about the current sample
+    key_wait: bool,      // FX0A is repeating, on_key_wait was called
+    mega: MegaChip,
+    program_start: usize,
+    font_start: usize,
 }
 
 impl Chip8 {
-    fn new(filename: &str) -> Self {
+    fn new(rom_data: &[u8], profile: &Profile, flags: [u8; FLAG_COUNT], limits: &Limits) -> Self {
         Chip8 {
-            memory: Self::_init_memory(filename),
+            memory: Self::_init_memory(rom_data, profile),
             gfx: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
-            screen_buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
-            pc: PROGRAM_START,
-            stack: Vec::with_capacity(12),
+            pc: profile.program_start,
+            stack: Vec::with_capacity(limits.stack_depth),
             v: [0; 16],
             keys: [false; 16],
             prev_keys: [false; 16],
             i: 0,
             delay_timer: 0,
             sound_timer: 0,
-            rng: rand::rng(),
-            window: Window::new(
-                "Rusty8",
-                SCREEN_WIDTH,
-                SCREEN_HEIGHT,
-                WindowOptions {
-                    scale: Scale::X16,
-                    ..WindowOptions::default()
-                },
-            )
-            .unwrap(),
+            rng: StdRng::from_os_rng(),
+            quirks: profile.quirks,
+            variant: profile.variant,
+            flags,
+            flags_dirty: false,
+            stack_depth: limits.stack_depth,
+            strict_memory: limits.strict_memory,
+            sound_playing: false,
+            key_wait: false,
+            mega: MegaChip::new(),
+            program_start: profile.program_start,
+            font_start: profile.font_start,
         }
     }
 
-    fn _init_memory(filename: &str) -> [u8; MEMORY_SIZE] {
-        let rom_data = fs::read(filename).expect("Failed to open ROM file");
+    fn _init_memory(rom_data: &[u8], profile: &Profile) -> Vec<u8> {
+        let memory_size = profile.variant.memory_size();
+        let program_start = profile.program_start;
+        let font_start = profile.font_start;
+
+        if program_start >= memory_size {
+            eprintln!(
+                "Start address {program_start:#05X} is outside of the {memory_size:#X} bytes of memory"
+            );
+            std::process::exit(1);
+        }
 
-        if rom_data.len() > (MEMORY_SIZE - PROGRAM_START) {
+        if rom_data.len() > memory_size - program_start {
             eprintln!("ROM file is too large to fit in memory");
             std::process::exit(1);
         }
 
-        let mut memory = [0u8; MEMORY_SIZE];
-
-        memory[PROGRAM_START..(PROGRAM_START + rom_data.len())].copy_from_slice(&rom_data);
-
-        memory[FONTSET_START..(FONTSET_START + 80)].copy_from_slice(&[
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ]);
+        // program_start + rom_data.len() fits, checked above; the font address
+        // comes straight from the command line or the database
+        let font_end = font_start.checked_add(FONTSET.len());
+        if font_end.is_none_or(|font_end| {
+            font_end > memory_size
+                || (font_start < program_start + rom_data.len() && program_start < font_end)
+        }) {
+            eprintln!("Font at {font_start:#05X} does not fit in memory or overlaps the ROM");
+            std::process::exit(1);
+        }
 
-        memory
-    }
+        let mut memory = vec![0u8; memory_size];
 
-    fn handle_input(&mut self) {
-        self.prev_keys.copy_from_slice(&self.keys);
+        memory[program_start..(program_start + rom_data.len())].copy_from_slice(rom_data);
 
-        self.keys[0x1] = self.window.is_key_down(minifb::Key::Key1);
-        self.keys[0x2] = self.window.is_key_down(minifb::Key::Key2);
-        self.keys[0x3] = self.window.is_key_down(minifb::Key::Key3);
-        self.keys[0xC] = self.window.is_key_down(minifb::Key::Key4);
+        memory[font_start..(font_start + FONTSET.len())].copy_from_slice(&FONTSET);
 
-        self.keys[0x4] = self.window.is_key_down(minifb::Key::Q);
-        self.keys[0x5] = self.window.is_key_down(minifb::Key::W);
-        self.keys[0x6] = self.window.is_key_down(minifb::Key::E);
-        self.keys[0xD] = self.window.is_key_down(minifb::Key::R);
+        memory
+    }
 
-        self.keys[0x7] = self.window.is_key_down(minifb::Key::A);
-        self.keys[0x8] = self.window.is_key_down(minifb::Key::S);
-        self.keys[0x9] = self.window.is_key_down(minifb::Key::D);
-        self.keys[0xE] = self.window.is_key_down(minifb::Key::F);
+    pub fn state(&self) -> State<'_> {
+        State {
+            pc: self.pc,
+            i: self.i,
+            v: &self.v,
+            stack: &self.stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            flags: &self.flags,
+            memory: &self.memory,
+            gfx: &self.gfx,
+        }
+    }
 
-        self.keys[0xA] = self.window.is_key_down(minifb::Key::Z);
-        self.keys[0x0] = self.window.is_key_down(minifb::Key::X);
-        self.keys[0xB] = self.window.is_key_down(minifb::Key::C);
-        self.keys[0xF] = self.window.is_key_down(minifb::Key::V);
+    fn handle_input(&mut self, frontend: &mut dyn Frontend) {
+        self.prev_keys.copy_from_slice(&self.keys);
+        frontend.read_keys(&mut self.keys);
     }
 
     fn update_timers(&mut self) {
@@ -121,16 +205,6 @@ impl Chip8 {
         self.sound_timer = self.sound_timer.saturating_sub(1);
     }
 
-    fn draw_to_screen(&mut self) {
-        for (i, &pixel) in self.gfx.iter().enumerate() {
-            self.screen_buffer[i] = if pixel == 0 { 0x000000 } else { 0xFFA500 };
-        }
-
-        self.window
-            .update_with_buffer(&self.screen_buffer, SCREEN_WIDTH, SCREEN_HEIGHT)
-            .unwrap();
-    }
-
     #[inline(always)]
     fn draw_sprite(&mut self, x: usize, y: usize, n: usize) {
         self.v[0xF] = 0;
@@ -167,14 +241,209 @@ impl Chip8 {
         }
     }
 
-    fn emulate_instruction(&mut self, how_many: usize) {
+    // like draw_sprite, but pixels past the screen edge wrap around to the other side
+    fn draw_sprite_wrapped(&mut self, x: usize, y: usize, n: usize) {
+        self.v[0xF] = 0;
+
+        for row in 0..n {
+            let y_coord = ((y + row) & (SCREEN_HEIGHT - 1)) * SCREEN_WIDTH;
+            let sprite_byte = self.memory[self.i + row];
+
+            (0..8)
+                .filter(|&bit| sprite_byte & (0x80 >> bit) != 0)
+                .for_each(|bit| {
+                    let idx = y_coord + ((x + bit) & (SCREEN_WIDTH - 1));
+                    self.v[0xF] |= self.gfx[idx];
+                    self.gfx[idx] ^= 1;
+                });
+        }
+    }
+
+    fn unknown_opcode(&self, opcode: u16, hooks: &mut dyn Hooks) {
+        hooks.on_unknown_opcode(self.pc - 2, opcode);
+    }
+
+    fn fault(&self, opcode: u16, kind: FaultKind) -> Fault {
+        Fault {
+            pc: self.pc - 2,
+            opcode,
+            kind,
+        }
+    }
+
+    fn check_read(&self, start: usize, len: usize) -> Result<(), FaultKind> {
+        if start + len > self.memory.len() {
+            return Err(FaultKind::OutOfBounds(start.max(self.memory.len())));
+        }
+        Ok(())
+    }
+
+    fn check_write(&self, start: usize, len: usize) -> Result<(), FaultKind> {
+        self.check_read(start, len)?;
+        if self.strict_memory && (start < self.program_start || self.overlaps_font(start, len)) {
+            return Err(FaultKind::ProtectedWrite(start));
+        }
+        Ok(())
+    }
+
+    fn overlaps_font(&self, start: usize, len: usize) -> bool {
+        start < self.font_start + FONTSET.len() && self.font_start < start + len
+    }
+
+    // DXYN in MegaChip mode, palette sprites of the size set by 03NN/04NN,
+    // font sprites (I inside the font) are drawn as 8xN 1-bit sprites
+    fn draw_mega_sprite(&mut self, opcode: u16) -> Result<(), Fault> {
+        let x = self.v[((opcode & 0x0F00) >> 8) as usize] as usize;
+        let y = self.v[((opcode & 0x00F0) >> 4) as usize] as usize;
+
+        let collision = if self.overlaps_font(self.i, 1) {
+            let n = (opcode & 0x000F) as usize;
+            self.check_read(self.i, n)
+                .map_err(|kind| self.fault(opcode, kind))?;
+            self.mega
+                .draw_font_sprite(x, y, &self.memory[self.i..self.i + n])
+        } else {
+            let size = self.mega.sprite_size();
+            self.check_read(self.i, size)
+                .map_err(|kind| self.fault(opcode, kind))?;
+            self.mega
+                .draw_sprite(x, y, &self.memory[self.i..self.i + size])
+        };
+
+        self.v[0xF] = collision as u8;
+        Ok(())
+    }
+
+    // 0x0NNN opcodes added by MegaChip
+    fn emulate_megachip(&mut self, opcode: u16, hooks: &mut dyn Hooks) -> Result<(), Fault> {
+        let nn = (opcode & 0x00FF) as usize;
+
+        match opcode {
+            // opcode 0x0010, leave MegaChip mode
+            0x0010 => self.mega.set_enabled(false),
+
+            // opcode 0x0011, enter MegaChip mode
+            0x0011 => self.mega.set_enabled(true),
+
+            // opcode 0x00BN, scroll up N lines
+            0x00B0..=0x00BF if self.mega.enabled => self.mega.scroll(0, -((nn & 0xF) as isize)),
+
+            // opcode 0x00CN, scroll down N lines
+            0x00C0..=0x00CF if self.mega.enabled => self.mega.scroll(0, (nn & 0xF) as isize),
+
+            // opcode 0x00FB, scroll right 4 pixels
+            0x00FB if self.mega.enabled => self.mega.scroll(4, 0),
+
+            // opcode 0x00FC, scroll left 4 pixels
+            0x00FC if self.mega.enabled => self.mega.scroll(-4, 0),
+
+            // opcode 0x01NN 0xNNNN, set I to the 24-bit address NNNNNN
+            0x0100..=0x01FF => {
+                self.check_read(self.pc, 2)
+                    .map_err(|kind| self.fault(opcode, kind))?;
+                let low = u16::from_be_bytes([self.memory[self.pc], self.memory[self.pc + 1]]);
+                self.i = (nn << 16) | low as usize;
+                self.pc += 2;
+            }
+
+            // opcode 0x02NN, load NN palette colors (ARGB) from I
+            0x0200..=0x02FF => {
+                self.check_read(self.i, nn * 4)
+                    .map_err(|kind| self.fault(opcode, kind))?;
+                self.mega
+                    .load_palette(&self.memory[self.i..self.i + nn * 4]);
+            }
+
+            // opcode 0x03NN, set sprite width to NN
+            0x0300..=0x03FF => self.mega.set_sprite_width(nn),
+
+            // opcode 0x04NN, set sprite height to NN
+            0x0400..=0x04FF => self.mega.set_sprite_height(nn),
+
+            // opcode 0x05NN, set screen alpha to NN, not emulated
+            0x0500..=0x05FF => {}
+
+            // opcode 0x060N, play digitized sound at I, N = 0 loops
+            0x0600..=0x060F => {
+                self.check_read(self.i, 6)
+                    .map_err(|kind| self.fault(opcode, kind))?;
+                self.mega.play_sample(&self.memory, self.i, nn == 0);
+            }
+
+            // opcode 0x0700, stop digitized sound
+            0x0700 => self.mega.stop_sample(),
+
+            // opcode 0x080N, set sprite blend mode
+            0x0800..=0x080F => self.mega.set_blend(nn as u8),
+
+            // opcode 0x09NN, set collision color index to NN
+            0x0900..=0x09FF => self.mega.set_collision_index(nn as u8),
+
+            _ => self.unknown_opcode(opcode, hooks),
+        }
+
+        Ok(())
+    }
+
+    // executes a single instruction, for tools that inspect the state after every step
+    pub fn step(&mut self, hooks: &mut dyn Hooks) -> Result<(), Fault> {
+        self.emulate_instruction(1, hooks)
+    }
+
+    // one 60 Hz frame: timers, instructions and the MegaChip sample, then the
+    // hooks get the sound state changes and the display
+    pub fn run_frame(&mut self, how_many: usize, hooks: &mut dyn Hooks) -> Result<(), Fault> {
+        self.update_timers();
+        self.emulate_instruction(how_many, hooks)?;
+        self.mega.update_sample(FPS_TARGET);
+
+        if let Some(sample) = &mut self.mega.sample {
+            if std::mem::take(&mut sample.new) || !self.sound_playing {
+                hooks.on_sound_start(Sound {
+                    data: sample.data(&self.memory),
+                    rate: sample.rate,
+                    looping: sample.looping,
+                });
+                self.sound_playing = true;
+            }
+        } else if self.sound_playing {
+            hooks.on_sound_stop();
+            self.sound_playing = false;
+        }
+
+        if self.mega.enabled {
+            let display = Display::Color {
+                pixels: &self.mega.front_buffer,
+                width: MEGA_WIDTH,
+                height: MEGA_HEIGHT,
+            };
+            hooks.on_draw(display, &self.keys);
+        } else {
+            hooks.on_draw(Display::Mono(&self.gfx), &self.keys);
+        }
+
+        Ok(())
+    }
+
+    fn emulate_instruction(&mut self, how_many: usize, hooks: &mut dyn Hooks) -> Result<(), Fault> {
         for _ in 0..how_many {
+            if self.pc + 1 >= self.memory.len() {
+                return Err(Fault {
+                    pc: self.pc,
+                    opcode: 0,
+                    kind: FaultKind::OutOfBounds(self.pc),
+                });
+            }
+
             let opcode = u16::from_be_bytes([self.memory[self.pc], self.memory[self.pc + 1]]);
             self.pc += 2;
 
             match opcode & 0xF000 {
                 // opcode 0x7XNN, add NN to register VX
-                0x7000 => self.v[((opcode & 0x0F00) >> 8) as usize] += (opcode & 0x00FF) as u8,
+                0x7000 => {
+                    let x = ((opcode & 0x0F00) >> 8) as usize;
+                    self.v[x] = self.v[x].wrapping_add((opcode & 0x00FF) as u8);
+                }
 
                 //opcode 0x4XNN, skip next instruction if VX != NN
                 0x4000 => {
@@ -183,18 +452,34 @@ impl Chip8 {
                     }
                 }
 
+                // opcode 0xDXYN in MegaChip mode, see draw_mega_sprite
+                0xD000 if self.mega.enabled => self.draw_mega_sprite(opcode)?,
+
                 // opcode 0xDXYN, draw sprite at coordinate (VX, VY) with height N
-                0xD000 => self.draw_sprite(
-                    self.v[((opcode & 0x0F00) >> 8) as usize] as usize & (SCREEN_WIDTH - 1),
-                    self.v[((opcode & 0x00F0) >> 4) as usize] as usize & (SCREEN_HEIGHT - 1),
-                    (opcode & 0x000F) as usize,
-                ),
+                0xD000 => {
+                    let x = self.v[((opcode & 0x0F00) >> 8) as usize] as usize & (SCREEN_WIDTH - 1);
+                    let y =
+                        self.v[((opcode & 0x00F0) >> 4) as usize] as usize & (SCREEN_HEIGHT - 1);
+                    let n = (opcode & 0x000F) as usize;
+                    self.check_read(self.i, n)
+                        .map_err(|kind| self.fault(opcode, kind))?;
+
+                    if self.quirks.clip_sprites {
+                        self.draw_sprite(x, y, n);
+                    } else {
+                        self.draw_sprite_wrapped(x, y, n);
+                    }
+                }
 
                 // opcode 0x1NNN, jump to address NNN
                 0x1000 => self.pc = (opcode & 0x0FFF) as usize,
 
                 //opcode 0x2NNN, call subroutine at address NNN
                 0x2000 => {
+                    if self.stack.len() == self.stack_depth {
+                        let kind = FaultKind::StackOverflow(self.stack_depth);
+                        return Err(self.fault(opcode, kind));
+                    }
                     self.stack.push(self.pc);
                     self.pc = (opcode & 0x0FFF) as usize;
                 }
@@ -229,21 +514,27 @@ impl Chip8 {
                     0x0001 => {
                         self.v[((opcode & 0x0F00) >> 8) as usize] |=
                             self.v[((opcode & 0x00F0) >> 4) as usize];
-                        self.v[0xF] = 0;
+                        if self.quirks.vf_reset {
+                            self.v[0xF] = 0;
+                        }
                     }
 
                     // opcode 0x8XY2, set VX to VX AND VY
                     0x0002 => {
                         self.v[((opcode & 0x0F00) >> 8) as usize] &=
                             self.v[((opcode & 0x00F0) >> 4) as usize];
-                        self.v[0xF] = 0;
+                        if self.quirks.vf_reset {
+                            self.v[0xF] = 0;
+                        }
                     }
 
                     // opcode 0x8XY3, set VX to VX XOR VY
                     0x0003 => {
                         self.v[((opcode & 0x0F00) >> 8) as usize] ^=
                             self.v[((opcode & 0x00F0) >> 4) as usize];
-                        self.v[0xF] = 0;
+                        if self.quirks.vf_reset {
+                            self.v[0xF] = 0;
+                        }
                     }
 
                     // opcode 0x8XY4, add VY to VX, set VF to 1 if overflow, else 0
@@ -264,12 +555,14 @@ impl Chip8 {
                         self.v[0xF] = (!underflow) as u8;
                     }
 
-                    // opcode 0x8XY6, shift VX right by 1
+                    // opcode 0x8XY6, shift VX (or VY, depending on quirk) right by 1
                     // set VF to least significant bit of VX before shift
                     0x0006 => {
                         let x = ((opcode & 0x0F00) >> 8) as usize;
                         let y = ((opcode & 0x00F0) >> 4) as usize;
-                        self.v[x] = self.v[y];
+                        if self.quirks.shift_uses_vy {
+                            self.v[x] = self.v[y];
+                        }
                         let overflow = self.v[x] & 0x1;
                         self.v[x] >>= 1;
                         self.v[0xF] = overflow;
@@ -285,18 +578,20 @@ impl Chip8 {
                         self.v[0xF] = (!underflow) as u8;
                     }
 
-                    // opcode 0x8XYE, set VX to VX << 1
+                    // opcode 0x8XYE, set VX to VX (or VY, depending on quirk) << 1
                     // set VF to most significant bit of VX before shift
                     0x000E => {
                         let x = ((opcode & 0x0F00) >> 8) as usize;
                         let y = ((opcode & 0x00F0) >> 4) as usize;
-                        self.v[x] = self.v[y];
+                        if self.quirks.shift_uses_vy {
+                            self.v[x] = self.v[y];
+                        }
                         let overflow = (self.v[x] & 0x80) >> 7;
                         self.v[x] <<= 1;
                         self.v[0xF] = overflow;
                     }
 
-                    _ => println!("Unknown opcode: {:#04X}", opcode),
+                    _ => self.unknown_opcode(opcode, hooks),
                 },
 
                 // opcode 0x9XY0, skip next instruction if VX != VY
@@ -308,21 +603,37 @@ impl Chip8 {
                     }
                 }
 
-                0x0000 => match opcode & 0x00FF {
+                0x0000 => match opcode {
                     // opcode 0x00E0, clear the display
+                    // in MegaChip mode, show the back buffer and clear it
+                    0x00E0 if self.mega.enabled => self.mega.present(),
                     0x00E0 => self.gfx.fill(0),
 
                     // opcode 0x00EE, return from subroutine
-                    0x00EE => self.pc = self.stack.pop().expect("Stack underflow"),
+                    0x00EE => match self.stack.pop() {
+                        Some(address) => self.pc = address,
+                        None => return Err(self.fault(opcode, FaultKind::StackUnderflow)),
+                    },
 
-                    _ => println!("Unknown opcode: {:#04X}", opcode),
+                    _ if self.variant == Variant::MegaChip => {
+                        self.emulate_megachip(opcode, hooks)?
+                    }
+
+                    _ => self.unknown_opcode(opcode, hooks),
                 },
 
                 // opcode 0xANNN, set index register I to NNN
                 0xA000 => self.i = (opcode & 0x0FFF) as usize,
 
-                // opcode 0xBNNN, jump to address NNN + V0
-                0xB000 => self.pc = (opcode & 0x0FFF) as usize + self.v[0] as usize,
+                // opcode 0xBNNN, jump to address NNN + V0 (or XNN + VX, depending on quirk)
+                0xB000 => {
+                    let offset = if self.quirks.jump_uses_vx {
+                        self.v[((opcode & 0x0F00) >> 8) as usize]
+                    } else {
+                        self.v[0]
+                    };
+                    self.pc = (opcode & 0x0FFF) as usize + offset as usize;
+                }
 
                 // opcode 0xCXNN, set VX to random byte AND NN
                 0xC000 => {
@@ -331,21 +642,21 @@ impl Chip8 {
                 }
 
                 0xE000 => match opcode & 0x00FF {
-                    // opcode 0xEX9E, skip next instruction if key with value VX is pressed
+                    // opcode 0xEX9E, skip next instruction if key with value VX (low nibble) is pressed
                     0x009E => {
-                        if self.keys[self.v[((opcode & 0x0F00) >> 8) as usize] as usize] {
+                        if self.keys[(self.v[((opcode & 0x0F00) >> 8) as usize] & 0xF) as usize] {
                             self.pc += 2;
                         }
                     }
 
-                    // opcode 0xEXA1, skip next instruction if key with value VX is not pressed
+                    // opcode 0xEXA1, skip next instruction if key with value VX (low nibble) is not pressed
                     0x00A1 => {
-                        if !self.keys[self.v[((opcode & 0x0F00) >> 8) as usize] as usize] {
+                        if !self.keys[(self.v[((opcode & 0x0F00) >> 8) as usize] & 0xF) as usize] {
                             self.pc += 2;
                         }
                     }
 
-                    _ => println!("Unknown opcode: {:#04X}", opcode),
+                    _ => self.unknown_opcode(opcode, hooks),
                 },
 
                 0xF000 => match opcode & 0x00FF {
@@ -362,8 +673,13 @@ impl Chip8 {
                             .find(|&(ref i, &key)| key && !self.keys[*i])
                         {
                             self.v[((opcode & 0x0F00) >> 8) as usize] = i as u8;
+                            self.key_wait = false;
                         } else {
                             self.pc -= 2; // repeat this instruction
+                            if !self.key_wait {
+                                self.key_wait = true;
+                                hooks.on_key_wait(((opcode & 0x0F00) >> 8) as usize);
+                            }
                         }
                     }
 
@@ -378,12 +694,14 @@ impl Chip8 {
 
                     // opcode 0xFX29, set I to location of sprite for digit VX
                     0x0029 => {
-                        self.i =
-                            FONTSET_START + (self.v[((opcode & 0x0F00) >> 8) as usize] as usize * 5)
+                        self.i = self.font_start
+                            + (self.v[((opcode & 0x0F00) >> 8) as usize] as usize * 5)
                     }
 
                     // opcode 0xFX33, store digits of VX in memory at addresses I, I+1, I+2
                     0x0033 => {
+                        self.check_write(self.i, 3)
+                            .map_err(|kind| self.fault(opcode, kind))?;
                         let value = self.v[((opcode & 0x0F00) >> 8) as usize];
                         self.memory[self.i] = value / 100;
                         self.memory[self.i + 1] = (value / 10) % 10;
@@ -393,32 +711,226 @@ impl Chip8 {
                     // opcode 0xFX55, store registers V0 to VX in memory starting at address I
                     0x0055 => {
                         let x = ((opcode & 0x0F00) >> 8) as usize;
+                        self.check_write(self.i, x + 1)
+                            .map_err(|kind| self.fault(opcode, kind))?;
                         self.memory[self.i..=self.i + x].copy_from_slice(&self.v[0..=x]);
-                        self.i += x + 1;
+                        if self.quirks.memory_increment {
+                            self.i += x + 1;
+                        }
                     }
 
                     // opcode 0xFX65, read registers V0 to VX from memory starting at address I
                     0x0065 => {
                         let x = ((opcode & 0x0F00) >> 8) as usize;
+                        self.check_read(self.i, x + 1)
+                            .map_err(|kind| self.fault(opcode, kind))?;
                         self.v[0..=x].copy_from_slice(&self.memory[self.i..=self.i + x]);
-                        self.i += x + 1;
+                        if self.quirks.memory_increment {
+                            self.i += x + 1;
+                        }
                     }
 
-                    _ => println!("Unknown opcode: {:#04X}", opcode),
+                    // opcode 0xFX75, store registers V0 to VX in the user flags (SCHIP and later)
+                    0x0075 if self.variant != Variant::Chip8 => {
+                        let x = ((opcode & 0x0F00) >> 8) as usize;
+                        self.flags[0..=x].copy_from_slice(&self.v[0..=x]);
+                        self.flags_dirty = true;
+                    }
+
+                    // opcode 0xFX85, read registers V0 to VX from the user flags (SCHIP and later)
+                    0x0085 if self.variant != Variant::Chip8 => {
+                        let x = ((opcode & 0x0F00) >> 8) as usize;
+                        self.v[0..=x].copy_from_slice(&self.flags[0..=x]);
+                    }
+
+                    _ => self.unknown_opcode(opcode, hooks),
                 },
 
-                _ => println!("Unknown opcode: {:#04X}", opcode),
+                _ => self.unknown_opcode(opcode, hooks),
             }
         }
+
+        Ok(())
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+struct Options {
+    rom_path: String,
+    use_rom_db: bool,
+    net_host: Option<u16>,
+    net_join: Option<String>,
+    tui: bool,
+    limits: Limits,
+    variant: Option<Variant>,
+    program_start: Option<usize>,
+    font_start: Option<usize>,
+    verify: bool,
+    fuzz: Option<usize>,
+    compare: Option<String>,
+}
 
-    if args.len() != 2 {
-        println!("Usage: {} <rom_file>", args[0]);
+fn parse_args() -> Options {
+    let args: Vec<String> = env::args().collect();
+    let usage = || -> ! {
+        println!(
+            "Usage: {} [--no-db] [--variant <name>] [--tui] [--host <port> | --join <host:port>] \
+             [--stack-depth <n>] [--strict | --permissive] \
+             [--start-address <addr>] [--font-address <addr>] [--verify | --compare <quirks>] <rom_file>\n       \
+             {} --fuzz <iterations> [--stack-depth <n>]",
+            args[0], args[0]
+        );
         std::process::exit(1);
+    };
+
+    let mut rom_path = None;
+    let mut use_rom_db = true;
+    let mut net_host = None;
+    let mut net_join = None;
+    let mut tui = false;
+    let mut variant = None;
+    let mut program_start = None;
+    let mut font_start = None;
+    let mut verify = false;
+    let mut fuzz = None;
+    let mut compare = None;
+    let mut limits = Limits {
+        stack_depth: STACK_DEPTH,
+        strict_memory: false,
+    };
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--no-db" => use_rom_db = false,
+            "--tui" => tui = true,
+            "--verify" => verify = true,
+            "--compare" => compare = Some(iter.next().cloned().unwrap_or_else(|| usage())),
+            "--fuzz" => {
+                fuzz = Some(
+                    iter.next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--start-address" => {
+                program_start = Some(
+                    iter.next()
+                        .and_then(|a| parse_number(a))
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--font-address" => {
+                font_start = Some(
+                    iter.next()
+                        .and_then(|a| parse_number(a))
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--variant" => {
+                variant = Some(
+                    iter.next()
+                        .and_then(|name| Variant::from_name(name))
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--strict" => limits.strict_memory = true,
+            "--permissive" => limits.strict_memory = false,
+            "--stack-depth" => {
+                limits.stack_depth = iter
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| (1..=MAX_STACK_DEPTH).contains(n))
+                    .unwrap_or_else(|| usage())
+            }
+            "--host" => {
+                net_host = Some(
+                    iter.next()
+                        .and_then(|p| p.parse().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            "--join" => net_join = Some(iter.next().cloned().unwrap_or_else(|| usage())),
+            flag if flag.starts_with("--") => usage(),
+            _ if rom_path.is_some() => usage(),
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+
+    if net_host.is_some() && net_join.is_some() {
+        usage();
+    }
+    if compare.is_some() && (verify || net_host.is_some() || net_join.is_some()) {
+        usage();
+    }
+
+    // the fuzzer generates its own programs
+    let rom_path = match fuzz {
+        Some(_) if rom_path.is_some() || verify => usage(),
+        Some(_) => String::new(),
+        None => rom_path.unwrap_or_else(|| usage()),
+    };
+
+    Options {
+        rom_path,
+        use_rom_db,
+        net_host,
+        net_join,
+        tui,
+        limits,
+        variant,
+        program_start,
+        font_start,
+        verify,
+        fuzz,
+        compare,
+    }
+}
+
+// everything besides the ROM that changes how it runs, both netplay sides must agree
+// (user flags are sent by the host instead, saved high scores differ)
+fn netplay_settings(profile: &Profile, limits: &Limits, cheats: &CheatList) -> String {
+    format!(
+        "{} quirks [{}] {} instructions/frame, start {:#05X}, font {:#05X}, stack {}{}, \
+         cheats {}",
+        profile.variant.name(),
+        profile.quirks.enabled().join(" "),
+        profile.instr_per_frame,
+        profile.program_start,
+        profile.font_start,
+        limits.stack_depth,
+        if limits.strict_memory { ", strict" } else { "" },
+        cheats.describe()
+    )
+}
+
+// screens is the number of CHIP-8 displays next to each other, the terminal fits two
+fn create_frontend(tui: bool, profile: &Profile, screens: usize) -> Box<dyn Frontend> {
+    if tui {
+        match TuiFrontend::new(profile.key_bindings.clone()) {
+            Ok(tui) => Box::new(tui),
+            Err(err) => {
+                eprintln!("Failed to set up the terminal: {err}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        let (width, height) = match profile.variant {
+            Variant::MegaChip => (MEGA_WIDTH, MEGA_HEIGHT),
+            _ => (SCREEN_WIDTH * screens, SCREEN_HEIGHT),
+        };
+        Box::new(WindowFrontend::new(
+            profile.key_bindings.clone(),
+            width,
+            height,
+        ))
+    }
+}
+
+fn main() {
+    let options = parse_args();
+
+    if let Some(iterations) = options.fuzz {
+        std::process::exit(verify::run_fuzz(iterations, options.limits.stack_depth));
     }
 
     let system_info = format!(
@@ -429,18 +941,152 @@ fn main() {
             .map_or_else(|| "n/a", |pbs| pbs.as_str())
     );
 
-    let mut interpreter = Chip8::new(&args[1]);
+    let rom_data = fs::read(&options.rom_path).expect("Failed to open ROM file");
+    let rom_hash = rom_hash(&rom_data);
+    let rom = rom::unpack(&rom_data).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+    let program = rom.code;
+
+    let db_profile = if options.use_rom_db {
+        RomDb::load().take(&rom_hash)
+    } else {
+        None
+    };
+    let mut profile = match db_profile {
+        Some(profile) => {
+            println!(
+                "ROM database: {} ({}, {} instructions/frame)",
+                profile.name.as_deref().unwrap_or(&rom_hash),
+                profile.variant.name(),
+                profile.instr_per_frame
+            );
+            profile
+        }
+        None => Profile::default(),
+    };
+    if let Some(variant) = options.variant {
+        profile.set_variant(variant);
+    }
+    if let Some(load_address) = rom.load_address {
+        profile.program_start = load_address;
+    }
+    if let Some(program_start) = options.program_start {
+        profile.program_start = program_start;
+    }
+    if let Some(font_start) = options.font_start {
+        profile.font_start = font_start;
+    }
+    let instr_per_frame = profile.instr_per_frame;
+
+    if options.verify {
+        std::process::exit(verify::run_verify(program, &profile, &options.limits));
+    }
+
+    if let Some(spec) = &options.compare {
+        let other = compare::other_profile(&profile, spec).unwrap_or_else(|err| {
+            eprintln!("--compare: {err}");
+            std::process::exit(1);
+        });
+        if profile.variant == Variant::MegaChip || other.variant == Variant::MegaChip {
+            eprintln!("--compare does not support MegaChip");
+            std::process::exit(1);
+        }
+
+        let mut frontend = create_frontend(options.tui, &profile, 2);
+        let report = compare::run_compare(
+            program,
+            &profile,
+            &other,
+            &options.limits,
+            !options.tui,
+            frontend.as_mut(),
+        );
+
+        drop(frontend);
+        // next to a window the divergence was printed when it happened,
+        // the terminal frontend covers the screen until it is closed
+        if options.tui || report.is_none() {
+            println!("Left:  {}", compare::describe(&profile));
+            println!("Right: {}", compare::describe(&other));
+            println!("{}", report.as_deref().unwrap_or("No divergence"));
+        }
+        return;
+    }
+
+    let flags = load_flags(&rom_hash);
+    let mut interpreter = Chip8::new(program, &profile, flags, &options.limits);
+    let mut cheats = CheatList::load(&options.rom_path, &rom_hash);
+    cheats.apply_on_load(&mut interpreter.memory);
+
+    // connect before the window opens, the host may wait a while for player 2
+    let settings = netplay_settings(&profile, &options.limits, &cheats);
+    let netplay = match (options.net_host, &options.net_join) {
+        (Some(port), _) => Some(
+            Netplay::host(port, &rom_hash, &settings, &flags)
+                .map(|(session, seed)| (session, seed, flags)),
+        ),
+        (_, Some(address)) => Some(Netplay::join(address, &rom_hash, &settings)),
+        _ => None,
+    };
+    let mut netplay = match netplay.transpose() {
+        Ok(Some((session, seed, host_flags))) => {
+            // both sides start from the host's high scores
+            interpreter.rng = StdRng::seed_from_u64(seed);
+            interpreter.flags = host_flags;
+            Some(session)
+        }
+        Ok(None) => None,
+        Err(err) => {
+            eprintln!("Netplay: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut frontend = create_frontend(options.tui, &profile, 1);
 
     let frame_time_target: Duration = Duration::from_secs_f64(1.0 / FPS_TARGET as f64);
     let mut last_title_update = std::time::Instant::now();
+    let mut fault = None;
+    let mut net_error = None;
 
-    while interpreter.window.is_open() {
+    while frontend.is_open() {
         let start_time = std::time::Instant::now();
 
-        interpreter.handle_input();
-        interpreter.update_timers();
-        interpreter.emulate_instruction(INSTR_PER_FRAME);
-        interpreter.draw_to_screen();
+        interpreter.handle_input(frontend.as_mut());
+
+        if let Some(session) = &mut netplay
+            && let Err(err) = session.sync_keys(&mut interpreter.keys)
+        {
+            net_error = Some(err);
+            break;
+        }
+
+        // a cheat toggled on one side only would desync the session
+        if netplay.is_none() {
+            for idx in 0..12 {
+                if frontend.cheat_hotkey_pressed(idx)
+                    && let Some(status) = cheats.toggle(idx, &mut interpreter.memory)
+                {
+                    frontend.set_status(&status);
+                }
+            }
+        }
+        cheats.apply_frozen(&mut interpreter.memory);
+
+        let result = interpreter.run_frame(instr_per_frame, frontend.as_mut());
+
+        // FX75 is rare, save right away so a killed process keeps the high score
+        if interpreter.flags_dirty {
+            save_flags(&rom_hash, &interpreter.flags);
+            interpreter.flags_dirty = false;
+        }
+
+        if let Err(err) = result {
+            fault = Some(err);
+            break;
+        }
 
         let frame_time = start_time.elapsed();
         let sleep_time = frame_time_target.saturating_sub(frame_time);
@@ -454,12 +1100,180 @@ fn main() {
             let status = format!(
                 "Rusty8 | FPS: {:.2} | MIPS: {:.2} | {}",
                 real_fps,
-                (INSTR_PER_FRAME as f64 * real_fps) / 1000000.0,
+                (instr_per_frame as f64 * real_fps) / 1000000.0,
                 system_info
             );
-            interpreter.window.set_title(&status);
-            println!("{status}");
+            frontend.set_status(&status);
             last_title_update = current_time;
         }
     }
+
+    // close the frontend first, the TUI would swallow the messages
+    drop(frontend);
+    if let Some(err) = net_error {
+        eprintln!("Netplay: connection lost ({err})");
+    }
+    if let Some(fault) = fault {
+        eprintln!("Emulation halted at {fault}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hooks::NoHooks;
+
+    // remembers the sound events
+    #[derive(Default)]
+    struct SoundLog(Vec<String>);
+
+    impl Hooks for SoundLog {
+        fn on_sound_start(&mut self, sound: Sound) {
+            self.0.push(format!(
+                "sample {} bytes at {} Hz, looping {}",
+                sound.data.len(),
+                sound.rate,
+                sound.looping
+            ));
+        }
+
+        fn on_sound_stop(&mut self) {
+            self.0.push("stop".to_string());
+        }
+    }
+
+    fn run_frames(program: &[u8], variant: Variant, frames: usize) -> Vec<String> {
+        let mut profile = Profile::default();
+        profile.set_variant(variant);
+        let limits = Limits {
+            stack_depth: STACK_DEPTH,
+            strict_memory: false,
+        };
+        let mut chip = Chip8::new(program, &profile, [0; FLAG_COUNT], &limits);
+
+        let mut log = SoundLog::default();
+        for frame in 0..frames {
+            if let Err(fault) = chip.run_frame(3, &mut log) {
+                panic!("{fault}");
+            }
+            log.0.push(format!("frame {frame}"));
+        }
+        log.0
+    }
+
+    #[test]
+    fn sound_timer_is_silent() {
+        // V0 = 2, ST = V0, loop
+        let program = [0x60, 0x02, 0xF0, 0x18, 0x12, 0x04];
+        assert_eq!(
+            run_frames(&program, Variant::Chip8, 2),
+            ["frame 0", "frame 1"]
+        );
+    }
+
+    #[test]
+    fn megachip_sample_plays_until_its_end() {
+        // I = 0x210, play once, loop; 256 bytes at 8000 Hz last two frames
+        let mut program = vec![0xA2, 0x10, 0x06, 0x01, 0x12, 0x04];
+        program.resize(0x10, 0);
+        program.extend([0x1F, 0x40, 0x00, 0x01, 0x00, 0x00]);
+        program.resize(0x10 + 6 + 256, 0x80);
+
+        assert_eq!(
+            run_frames(&program, Variant::MegaChip, 3),
+            [
+                "sample 256 bytes at 8000 Hz, looping false",
+                "frame 0",
+                "stop",
+                "frame 1",
+                "frame 2"
+            ]
+        );
+    }
+
+    fn machine(
+        program: &[u8],
+        profile: &Profile,
+        stack_depth: usize,
+        strict_memory: bool,
+    ) -> Chip8 {
+        let limits = Limits {
+            stack_depth,
+            strict_memory,
+        };
+        Chip8::new(program, profile, [0; FLAG_COUNT], &limits)
+    }
+
+    #[test]
+    fn call_past_the_stack_limit_faults() {
+        // call itself forever
+        let program = [0x22, 0x00];
+        let mut chip = machine(&program, &Profile::default(), 4, false);
+
+        let Err(fault) = chip.run_frame(10, &mut NoHooks) else {
+            panic!("stack overflow did not fault");
+        };
+        assert_eq!(fault.kind, FaultKind::StackOverflow(4));
+        assert_eq!(fault.pc, 0x200);
+        assert_eq!(chip.stack.len(), 4);
+    }
+
+    #[test]
+    fn return_without_call_faults() {
+        let program = [0x00, 0xEE];
+        let mut chip = machine(&program, &Profile::default(), STACK_DEPTH, false);
+
+        let Err(fault) = chip.run_frame(1, &mut NoHooks) else {
+            panic!("stack underflow did not fault");
+        };
+        assert_eq!(fault.kind, FaultKind::StackUnderflow);
+        assert_eq!(fault.pc, 0x200);
+    }
+
+    // I = 0x100, V0 = 1, store V0 at I, loop
+    const WRITE_BELOW_PROGRAM: [u8; 8] = [0xA1, 0x00, 0x60, 0x01, 0xF0, 0x55, 0x12, 0x06];
+
+    #[test]
+    fn strict_memory_protects_the_interpreter_area() {
+        let mut chip = machine(&WRITE_BELOW_PROGRAM, &Profile::default(), STACK_DEPTH, true);
+
+        let Err(fault) = chip.run_frame(4, &mut NoHooks) else {
+            panic!("write below the program did not fault");
+        };
+        assert_eq!(fault.kind, FaultKind::ProtectedWrite(0x100));
+        assert_eq!(fault.pc, 0x204);
+        assert_eq!(chip.memory[0x100], 0);
+    }
+
+    #[test]
+    fn permissive_memory_allows_the_same_write() {
+        let mut chip = machine(
+            &WRITE_BELOW_PROGRAM,
+            &Profile::default(),
+            STACK_DEPTH,
+            false,
+        );
+
+        if let Err(fault) = chip.run_frame(4, &mut NoHooks) {
+            panic!("{fault}");
+        }
+        assert_eq!(chip.memory[0x100], 1);
+    }
+
+    #[test]
+    fn strict_memory_protects_a_moved_font() {
+        // I = 0xF00, V0 = 1, store V0 at I
+        let program = [0xAF, 0x00, 0x60, 0x01, 0xF0, 0x55];
+        let profile = Profile {
+            font_start: 0xF00,
+            ..Profile::default()
+        };
+        let mut chip = machine(&program, &profile, STACK_DEPTH, true);
+
+        let Err(fault) = chip.run_frame(3, &mut NoHooks) else {
+            panic!("write to the font did not fault");
+        };
+        assert_eq!(fault.kind, FaultKind::ProtectedWrite(0xF00));
+        assert_eq!(fault.pc, 0x204);
+    }
 }
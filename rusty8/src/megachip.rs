@@ -0,0 +1,264 @@
+// MegaChip extension: 256x192 display with 8-bit palette indices, sprites of
+// any size that blit palette indices, 24-bit I register and digitized sound.
+//
+// The machine starts in normal CHIP-8 mode, 0011 switches to MegaChip mode and
+// 0010 back. In MegaChip mode drawing goes to a back buffer and 00E0 shows it.
+// 060N/0700 start and stop samples, the frontends play them through the sound
+// hooks while the position kept here decides when a sample has ended.
+
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+pub const MEGA_MEMORY_SIZE: usize = 0x100_0000;
+pub const MEGA_INSTR_PER_FRAME: usize = 1000;
+
+#[derive(Clone, Copy)]
+enum Blend {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Alpha75,
+    Add,
+    Multiply,
+}
+
+// the playing sample, 8-bit unsigned mono
+pub struct Sample {
+    start: usize, // address of the first sample byte
+    length: usize,
+    pub rate: usize, // samples per second
+    pub looping: bool,
+    position: usize,
+    pub new: bool, // started since the frontend was last told
+}
+
+impl Sample {
+    pub fn data<'a>(&self, memory: &'a [u8]) -> &'a [u8] {
+        &memory[self.start..self.start + self.length]
+    }
+}
+
+pub struct MegaChip {
+    pub enabled: bool,
+    indices: Vec<u8>,           // palette index per pixel, for collisions
+    back_buffer: Vec<u32>,      // 0RGB, drawn to by DXYN
+    pub front_buffer: Vec<u32>, // 0RGB, what the frontend shows
+    palette: [u32; 256],
+    sprite_width: usize,
+    sprite_height: usize,
+    blend: Blend,
+    collision_index: u8,
+    pub sample: Option<Sample>,
+}
+
+impl MegaChip {
+    pub fn new() -> Self {
+        MegaChip {
+            enabled: false,
+            indices: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            back_buffer: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            front_buffer: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            palette: [0xFFFFFF; 256],
+            sprite_width: 8,
+            sprite_height: 8,
+            blend: Blend::Normal,
+            collision_index: 0,
+            sample: None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.indices.fill(0);
+        self.back_buffer.fill(0);
+    }
+
+    // 0011/0010, switching modes clears the screen
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+        self.front_buffer.fill(0);
+    }
+
+    // 00E0 in MegaChip mode, shows the back buffer and starts a new frame
+    pub fn present(&mut self) {
+        self.front_buffer.copy_from_slice(&self.back_buffer);
+        self.clear();
+    }
+
+    // 02NN, NN colors as ARGB byte quadruples, starting at palette index 1
+    pub fn load_palette(&mut self, data: &[u8]) {
+        for (idx, argb) in data.chunks_exact(4).enumerate() {
+            self.palette[idx + 1] = u32::from_be_bytes([0, argb[1], argb[2], argb[3]]);
+        }
+    }
+
+    // 03NN/04NN, 0 means 256
+    pub fn set_sprite_width(&mut self, width: usize) {
+        self.sprite_width = if width == 0 { 256 } else { width };
+    }
+
+    pub fn set_sprite_height(&mut self, height: usize) {
+        self.sprite_height = if height == 0 { 256 } else { height };
+    }
+
+    pub fn sprite_size(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    // 080N, unknown modes fall back to normal blending
+    pub fn set_blend(&mut self, mode: u8) {
+        self.blend = match mode {
+            1 => Blend::Alpha25,
+            2 => Blend::Alpha50,
+            3 => Blend::Alpha75,
+            4 => Blend::Add,
+            5 => Blend::Multiply,
+            _ => Blend::Normal,
+        };
+    }
+
+    // 09NN
+    pub fn set_collision_index(&mut self, index: u8) {
+        self.collision_index = index;
+    }
+
+    // 060N, the sample header is rate (16 bit), length (24 bit) and a zero byte
+    pub fn play_sample(&mut self, memory: &[u8], address: usize, looping: bool) {
+        let header = &memory[address..address + 6];
+        let rate = u16::from_be_bytes([header[0], header[1]]) as usize;
+        let length = u32::from_be_bytes([0, header[2], header[3], header[4]]) as usize;
+
+        self.sample = Some(Sample {
+            start: address + 6,
+            length: length.min(memory.len() - address - 6),
+            rate,
+            looping,
+            position: 0,
+            new: true,
+        });
+    }
+
+    // 0700
+    pub fn stop_sample(&mut self) {
+        self.sample = None;
+    }
+
+    // advances the playing sample by one frame of the given frame rate
+    pub fn update_sample(&mut self, fps: usize) {
+        let Some(sample) = &mut self.sample else {
+            return;
+        };
+
+        sample.position += sample.rate / fps;
+        if sample.position >= sample.length {
+            if sample.looping && sample.length > 0 {
+                sample.position %= sample.length;
+            } else {
+                self.sample = None;
+            }
+        }
+    }
+
+    // DXYN in MegaChip mode, sprite holds sprite_width * sprite_height palette
+    // indices, index 0 is transparent. Returns true on collision.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let mut collision = false;
+
+        for (row, line) in sprite.chunks_exact(self.sprite_width).enumerate() {
+            let py = y + row;
+            if py >= MEGA_HEIGHT {
+                break;
+            }
+
+            for (col, &index) in line.iter().enumerate() {
+                let px = x + col;
+                if px >= MEGA_WIDTH {
+                    break;
+                }
+                if index == 0 {
+                    continue;
+                }
+
+                let pixel = py * MEGA_WIDTH + px;
+                collision |= self.indices[pixel] == self.collision_index;
+                self.indices[pixel] = index;
+                self.back_buffer[pixel] = blend(
+                    self.blend,
+                    self.back_buffer[pixel],
+                    self.palette[index as usize],
+                );
+            }
+        }
+
+        collision
+    }
+
+    // DXYN with I in the font area, the 8xN 1-bit sprite is drawn in white
+    pub fn draw_font_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        let mut collision = false;
+
+        for (row, &byte) in sprite.iter().enumerate() {
+            let py = y + row;
+            if py >= MEGA_HEIGHT {
+                break;
+            }
+
+            for bit in (0..8).filter(|&bit| byte & (0x80 >> bit) != 0) {
+                let px = x + bit;
+                if px >= MEGA_WIDTH {
+                    break;
+                }
+
+                let pixel = py * MEGA_WIDTH + px;
+                collision |= self.indices[pixel] != 0;
+                self.indices[pixel] = 0xFF;
+                self.back_buffer[pixel] = 0xFFFFFF;
+            }
+        }
+
+        collision
+    }
+
+    // 00BN/00CN/00FB/00FC, positive dx scrolls right, positive dy down
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        shift(&mut self.back_buffer, dx, dy);
+        shift(&mut self.indices, dx, dy);
+    }
+}
+
+// moves the content of a MEGA_WIDTH x MEGA_HEIGHT buffer, uncovered pixels become 0
+fn shift<T: Copy + Default>(buffer: &mut [T], dx: isize, dy: isize) {
+    let old = buffer.to_vec();
+
+    for (y, row) in buffer.chunks_exact_mut(MEGA_WIDTH).enumerate() {
+        let src_y = y as isize - dy;
+
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let src_x = x as isize - dx;
+
+            *pixel = if (0..MEGA_WIDTH as isize).contains(&src_x)
+                && (0..MEGA_HEIGHT as isize).contains(&src_y)
+            {
+                old[src_y as usize * MEGA_WIDTH + src_x as usize]
+            } else {
+                T::default()
+            };
+        }
+    }
+}
+
+fn blend(mode: Blend, dst: u32, src: u32) -> u32 {
+    let per_channel = |f: fn(u32, u32) -> u32| {
+        (0..3)
+            .map(|c| f((src >> (c * 8)) & 0xFF, (dst >> (c * 8)) & 0xFF).min(0xFF) << (c * 8))
+            .fold(0, |color, channel| color | channel)
+    };
+
+    match mode {
+        Blend::Normal => src,
+        Blend::Alpha25 => per_channel(|s, d| (s + 3 * d) / 4),
+        Blend::Alpha50 => per_channel(|s, d| (s + d) / 2),
+        Blend::Alpha75 => per_channel(|s, d| (3 * s + d) / 4),
+        Blend::Add => per_channel(|s, d| s + d),
+        Blend::Multiply => per_channel(|s, d| s * d / 0xFF),
+    }
+}
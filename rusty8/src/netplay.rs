@@ -0,0 +1,179 @@
+// Experimental two-player netplay over TCP.
+//
+// The host listens, the guest connects and sends the SHA-1 of its ROM and a
+// description of everything else that affects emulation (variant, quirks, speed,
+// cheats, see settings in main.rs). Both have to match the host's, otherwise the
+// machines would drift apart. The host answers with the RNG seed both sides use,
+// so CXNN produces the same numbers, and its saved user flags, which replace the
+// guest's for the session.
+// Afterwards both instances exchange their half of the keypad once per frame and
+// block until the other side's keys arrive, which keeps them in lockstep.
+//
+// The host owns the two left keypad columns, the guest the two right ones:
+//
+//     host      guest
+//     1 2       3 C
+//     4 5       6 D
+//     7 8       9 E
+//     A 0       B F
+
+use crate::flags::FLAG_COUNT;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const MAGIC: &[u8; 6] = b"RUSTY8";
+
+// handshake answers of the host
+const ACCEPTED: u8 = 1;
+const OTHER_ROM: u8 = 0;
+const OTHER_SETTINGS: u8 = 2;
+const HOST_KEYS: u16 =
+    1 << 0x1 | 1 << 0x2 | 1 << 0x4 | 1 << 0x5 | 1 << 0x7 | 1 << 0x8 | 1 << 0xA | 1 << 0x0;
+
+pub struct Netplay {
+    stream: TcpStream,
+    local_mask: u16,
+}
+
+impl Netplay {
+    // waits for a guest, returns the session and the shared RNG seed
+    pub fn host(
+        port: u16,
+        rom_hash: &str,
+        settings: &str,
+        flags: &[u8; FLAG_COUNT],
+    ) -> io::Result<(Self, u64)> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        println!("Netplay: waiting for player 2 on port {port}");
+
+        let (mut stream, peer) = listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        let mut hello = [0u8; MAGIC.len() + 40];
+        stream.read_exact(&mut hello)?;
+
+        if &hello[..MAGIC.len()] != MAGIC || &hello[MAGIC.len()..] != rom_hash.as_bytes() {
+            stream.write_all(&[OTHER_ROM])?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{peer} is not running the same ROM"),
+            ));
+        }
+
+        let guest_settings = read_string(&mut stream)?;
+        if guest_settings != settings {
+            stream.write_all(&[OTHER_SETTINGS])?;
+            write_string(&mut stream, settings)?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{peer} runs the ROM with other settings\n  here:     {settings}\n  \
+                     player 2: {guest_settings}"
+                ),
+            ));
+        }
+
+        let seed = rand::random::<u64>();
+        stream.write_all(&[ACCEPTED])?;
+        stream.write_all(&seed.to_be_bytes())?;
+        stream.write_all(flags)?;
+        println!("Netplay: player 2 connected from {peer}");
+
+        Ok((
+            Netplay {
+                stream,
+                local_mask: HOST_KEYS,
+            },
+            seed,
+        ))
+    }
+
+    // returns the session, the shared RNG seed and the host's user flags
+    pub fn join(
+        address: &str,
+        rom_hash: &str,
+        settings: &str,
+    ) -> io::Result<(Self, u64, [u8; FLAG_COUNT])> {
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+
+        stream.write_all(MAGIC)?;
+        stream.write_all(rom_hash.as_bytes())?;
+        write_string(&mut stream, settings)?;
+
+        let mut answer = [0u8; 1];
+        stream.read_exact(&mut answer)?;
+        match answer[0] {
+            ACCEPTED => {}
+            OTHER_SETTINGS => {
+                let host_settings = read_string(&mut stream)?;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "host runs the ROM with other settings\n  here: {settings}\n  host: \
+                         {host_settings}"
+                    ),
+                ));
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "host is not running the same ROM",
+                ));
+            }
+        }
+
+        let mut seed = [0u8; 8];
+        stream.read_exact(&mut seed)?;
+        let mut flags = [0u8; FLAG_COUNT];
+        stream.read_exact(&mut flags)?;
+        println!("Netplay: connected to {address} as player 2");
+
+        Ok((
+            Netplay {
+                stream,
+                local_mask: !HOST_KEYS,
+            },
+            u64::from_be_bytes(seed),
+            flags,
+        ))
+    }
+
+    // sends our half of the keypad, blocks for the other half and merges both into keys
+    pub fn sync_keys(&mut self, keys: &mut [bool; 16]) -> io::Result<()> {
+        let local = keys
+            .iter()
+            .enumerate()
+            .fold(0u16, |mask, (i, &down)| mask | ((down as u16) << i))
+            & self.local_mask;
+
+        self.stream.write_all(&local.to_be_bytes())?;
+
+        let mut remote = [0u8; 2];
+        self.stream.read_exact(&mut remote)?;
+        let remote = u16::from_be_bytes(remote) & !self.local_mask;
+
+        let merged = local | remote;
+        for (i, key) in keys.iter_mut().enumerate() {
+            *key = merged & (1 << i) != 0;
+        }
+
+        Ok(())
+    }
+}
+
+// u16 length, then the UTF-8 bytes
+fn write_string(stream: &mut TcpStream, text: &str) -> io::Result<()> {
+    let len = u16::try_from(text.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "settings too long"))?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(text.as_bytes())
+}
+
+fn read_string(stream: &mut TcpStream) -> io::Result<String> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut text = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut text)?;
+    String::from_utf8(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -0,0 +1,171 @@
+// On-screen keypad, composited into the upscaled framebuffer of the window
+// frontend. Shows the CHIP-8 key, the physical keys bound to it, and lights up
+// while the key is down.
+
+use crate::keymap::{HostKey, KeyBindings};
+
+const CELL: usize = 48;
+const GAP: usize = 4;
+const MARGIN: usize = 16;
+const PANEL: usize = 4 * CELL + 5 * GAP;
+
+const PANEL_COLOR: u32 = 0x202020;
+const CELL_COLOR: u32 = 0x404040;
+const PRESSED_COLOR: u32 = 0xFFA500;
+const TEXT_COLOR: u32 = 0xFFFFFF;
+const LABEL_COLOR: u32 = 0xB0B0B0;
+
+// keypad layout, row by row
+const LAYOUT: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xC, //
+    0x4, 0x5, 0x6, 0xD, //
+    0x7, 0x8, 0x9, 0xE, //
+    0xA, 0x0, 0xB, 0xF, //
+];
+
+// draws the keypad into the bottom right corner of buffer
+pub fn draw_keypad(
+    buffer: &mut [u32],
+    width: usize,
+    height: usize,
+    key_bindings: &KeyBindings,
+    keys: &[bool; 16],
+) {
+    let panel_x = width - PANEL - MARGIN;
+    let panel_y = height - PANEL - MARGIN;
+
+    // darken the game behind the panel instead of hiding it
+    for y in panel_y..panel_y + PANEL {
+        for pixel in &mut buffer[y * width + panel_x..][..PANEL] {
+            *pixel = (*pixel >> 2 & 0x3F3F3F) + PANEL_COLOR;
+        }
+    }
+
+    for (slot, &chip_key) in LAYOUT.iter().enumerate() {
+        let cell_x = panel_x + GAP + (slot % 4) * (CELL + GAP);
+        let cell_y = panel_y + GAP + (slot / 4) * (CELL + GAP);
+
+        let color = if keys[chip_key] {
+            PRESSED_COLOR
+        } else {
+            CELL_COLOR
+        };
+        for y in cell_y..cell_y + CELL {
+            buffer[y * width + cell_x..][..CELL].fill(color);
+        }
+
+        let digit = char::from_digit(chip_key as u32, 16)
+            .unwrap()
+            .to_ascii_uppercase();
+        draw_text(
+            buffer,
+            width,
+            cell_x + 18,
+            cell_y + 5,
+            4,
+            TEXT_COLOR,
+            &digit.to_string(),
+        );
+
+        let label: String = key_bindings
+            .iter()
+            .filter(|&&(_, key)| key == chip_key)
+            .map(|&(host_key, _)| key_label(host_key))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let label: String = label.chars().take(CELL / 8).collect();
+        let label_x = cell_x + (CELL - label.chars().count() * 8 + 2) / 2;
+        draw_text(
+            buffer,
+            width,
+            label_x,
+            cell_y + CELL - 13,
+            2,
+            LABEL_COLOR,
+            &label,
+        );
+    }
+}
+
+fn key_label(key: HostKey) -> String {
+    match key {
+        HostKey::Char(c) => c.to_ascii_uppercase().to_string(),
+        HostKey::Up => "↑".to_string(),
+        HostKey::Down => "↓".to_string(),
+        HostKey::Left => "←".to_string(),
+        HostKey::Right => "→".to_string(),
+        HostKey::Space => "SP".to_string(),
+        HostKey::Enter => "EN".to_string(),
+    }
+}
+
+// draws text with the 3x5 font below, each font pixel becomes a scale x scale block
+fn draw_text(
+    buffer: &mut [u32],
+    width: usize,
+    x: usize,
+    y: usize,
+    scale: usize,
+    color: u32,
+    text: &str,
+) {
+    for (idx, c) in text.chars().enumerate() {
+        let glyph = glyph(c);
+        let glyph_x = x + idx * 4 * scale;
+
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in (0..3).filter(|col| bits & (0b100 >> col) != 0) {
+                for dy in 0..scale {
+                    let start = (y + row * scale + dy) * width + glyph_x + col * scale;
+                    buffer[start..start + scale].fill(color);
+                }
+            }
+        }
+    }
+}
+
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b100, 0b100],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '↑' => [0b010, 0b111, 0b010, 0b010, 0b010],
+        '↓' => [0b010, 0b010, 0b010, 0b111, 0b010],
+        '←' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '→' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        _ => [0; 5],
+    }
+}
@@ -0,0 +1,15 @@
+use std::env;
+use std::path::PathBuf;
+
+// per-user directory for emulator data (cheats, saves, config)
+// %APPDATA%\rusty8 on Windows, $XDG_CONFIG_HOME/rusty8 or ~/.config/rusty8 elsewhere
+pub fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("rusty8"));
+    }
+
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("rusty8"))
+}
@@ -0,0 +1,150 @@
+// Behaviour differences between the CHIP-8 family members.
+// Besides FX75/FX85 and the MegaChip extension (megachip.rs) only the CHIP-8
+// instruction set is implemented, the variant mostly picks the quirk preset.
+
+use crate::megachip::{MEGA_INSTR_PER_FRAME, MEGA_MEMORY_SIZE};
+use crate::{INSTR_PER_FRAME, MEMORY_SIZE};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+    MegaChip,
+}
+
+impl Variant {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "chip8" => Some(Variant::Chip8),
+            "schip" => Some(Variant::SuperChip),
+            "xochip" => Some(Variant::XoChip),
+            "megachip" => Some(Variant::MegaChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Chip8 => "chip8",
+            Variant::SuperChip => "schip",
+            Variant::XoChip => "xochip",
+            Variant::MegaChip => "megachip",
+        }
+    }
+
+    pub fn memory_size(self) -> usize {
+        match self {
+            Variant::MegaChip => MEGA_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    pub fn default_instr_per_frame(self) -> usize {
+        match self {
+            Variant::MegaChip => MEGA_INSTR_PER_FRAME,
+            _ => INSTR_PER_FRAME,
+        }
+    }
+
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks {
+                vf_reset: true,
+                memory_increment: true,
+                shift_uses_vy: true,
+                clip_sprites: true,
+                jump_uses_vx: false,
+            },
+            Variant::SuperChip | Variant::MegaChip => Quirks {
+                vf_reset: false,
+                memory_increment: false,
+                shift_uses_vy: false,
+                clip_sprites: true,
+                jump_uses_vx: true,
+            },
+            Variant::XoChip => Quirks {
+                vf_reset: false,
+                memory_increment: true,
+                shift_uses_vy: true,
+                clip_sprites: false,
+                jump_uses_vx: false,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Quirks {
+    pub vf_reset: bool,         // 8XY1, 8XY2, 8XY3 reset VF to 0
+    pub memory_increment: bool, // FX55, FX65 leave I pointing behind the last register
+    pub shift_uses_vy: bool,    // 8XY6, 8XYE shift VY into VX instead of shifting VX in place
+    pub clip_sprites: bool,     // sprites are clipped at the screen edge instead of wrapping
+    pub jump_uses_vx: bool,     // BXNN jumps to XNN + VX instead of NNN + V0
+}
+
+impl Quirks {
+    // toggles a quirk by its config name, returns false for unknown names
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        let quirk = match name {
+            "vf_reset" => &mut self.vf_reset,
+            "memory_increment" => &mut self.memory_increment,
+            "shift_uses_vy" => &mut self.shift_uses_vy,
+            "clip_sprites" => &mut self.clip_sprites,
+            "jump_uses_vx" => &mut self.jump_uses_vx,
+            _ => return false,
+        };
+
+        *quirk = enabled;
+        true
+    }
+
+    // applies a "+name" or "-name" token, a bare name enables the quirk
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        let (enabled, name) = match spec.strip_prefix('-') {
+            Some(name) => (false, name),
+            None => (true, spec.strip_prefix('+').unwrap_or(spec)),
+        };
+        if self.set(name, enabled) {
+            Ok(())
+        } else {
+            Err(format!("unknown quirk '{name}'"))
+        }
+    }
+
+    // the enabled quirks by config name, for messages
+    pub fn enabled(&self) -> Vec<&'static str> {
+        [
+            ("vf_reset", self.vf_reset),
+            ("memory_increment", self.memory_increment),
+            ("shift_uses_vy", self.shift_uses_vy),
+            ("clip_sprites", self.clip_sprites),
+            ("jump_uses_vx", self.jump_uses_vx),
+        ]
+        .into_iter()
+        .filter(|&(_, enabled)| enabled)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_toggles_by_name() {
+        let mut quirks = Variant::Chip8.default_quirks();
+        quirks.apply("-vf_reset").unwrap();
+        quirks.apply("+jump_uses_vx").unwrap();
+        quirks.apply("clip_sprites").unwrap();
+        assert!(!quirks.vf_reset);
+        assert!(quirks.jump_uses_vx);
+        assert!(quirks.clip_sprites);
+
+        assert_eq!(
+            quirks.apply("+wrap_sprites"),
+            Err("unknown quirk 'wrap_sprites'".to_string())
+        );
+    }
+}
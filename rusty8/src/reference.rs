@@ -0,0 +1,311 @@
+// A deliberately plain CHIP-8 interpreter used as the reference in --verify
+// and --fuzz. It favours obviously correct code over speed: every opcode is
+// decoded into its fields first and handled in one flat match, memory and
+// display accesses are written out without fast paths. Keep it that way, it is
+// only useful as long as it is easy to check against the specification.
+//
+// MegaChip is not covered.
+
+use crate::diff::State;
+use crate::fault::FaultKind;
+use crate::flags::FLAG_COUNT;
+use crate::quirks::{Quirks, Variant};
+use crate::romdb::Profile;
+use crate::{FONTSET, Limits, SCREEN_HEIGHT, SCREEN_WIDTH};
+use rand::prelude::*;
+
+pub struct Reference {
+    pub memory: Vec<u8>,
+    pub gfx: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: usize,
+    pub pc: usize,
+    pub stack: Vec<usize>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: [bool; 16],
+    pub prev_keys: [bool; 16],
+    pub flags: [u8; FLAG_COUNT],
+    pub rng: StdRng,
+    pub quirks: Quirks,
+    pub variant: Variant,
+    pub stack_depth: usize,
+    pub strict_memory: bool,
+    pub program_start: usize,
+    pub font_start: usize,
+}
+
+impl Reference {
+    pub fn new(program: &[u8], profile: &Profile, limits: &Limits, seed: u64) -> Self {
+        let mut memory = vec![0; profile.variant.memory_size()];
+        memory[profile.program_start..profile.program_start + program.len()]
+            .copy_from_slice(program);
+        memory[profile.font_start..profile.font_start + FONTSET.len()].copy_from_slice(&FONTSET);
+
+        Reference {
+            memory,
+            gfx: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            v: [0; 16],
+            i: 0,
+            pc: profile.program_start,
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            keys: [false; 16],
+            prev_keys: [false; 16],
+            flags: [0; FLAG_COUNT],
+            rng: StdRng::seed_from_u64(seed),
+            quirks: profile.quirks,
+            variant: profile.variant,
+            stack_depth: limits.stack_depth,
+            strict_memory: limits.strict_memory,
+            program_start: profile.program_start,
+            font_start: profile.font_start,
+        }
+    }
+
+    pub fn state(&self) -> State<'_> {
+        State {
+            pc: self.pc,
+            i: self.i,
+            v: &self.v,
+            stack: &self.stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            flags: &self.flags,
+            memory: &self.memory,
+            gfx: &self.gfx,
+        }
+    }
+
+    pub fn update_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc += 2;
+        }
+    }
+
+    fn read(&self, address: usize) -> Result<u8, FaultKind> {
+        match self.memory.get(address) {
+            Some(&byte) => Ok(byte),
+            None => Err(FaultKind::OutOfBounds(address)),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: u8) -> Result<(), FaultKind> {
+        if address >= self.memory.len() {
+            return Err(FaultKind::OutOfBounds(address));
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    // accesses are checked as a whole before anything is changed
+    fn check_range(&self, start: usize, len: usize, write: bool) -> Result<(), FaultKind> {
+        if start + len > self.memory.len() {
+            return Err(FaultKind::OutOfBounds(start.max(self.memory.len())));
+        }
+        let font = self.font_start..self.font_start + FONTSET.len();
+        let protected =
+            start < self.program_start || (start < font.end && font.start < start + len);
+        if write && self.strict_memory && protected {
+            return Err(FaultKind::ProtectedWrite(start));
+        }
+        Ok(())
+    }
+
+    // returns the PC of the faulting instruction along with the fault
+    pub fn step(&mut self) -> Result<(), (usize, FaultKind)> {
+        let pc = self.pc;
+
+        if pc + 1 >= self.memory.len() {
+            return Err((pc, FaultKind::OutOfBounds(pc)));
+        }
+
+        let opcode = (self.read(pc).unwrap() as u16) << 8 | self.read(pc + 1).unwrap() as u16;
+        self.pc += 2;
+
+        self.execute(opcode).map_err(|kind| (pc, kind))
+    }
+
+    fn execute(&mut self, opcode: u16) -> Result<(), FaultKind> {
+        let op = (opcode >> 12) as u8;
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = (opcode & 0xFFF) as usize;
+
+        match (op, n) {
+            (0x0, _) if opcode == 0x00E0 => {
+                for pixel in self.gfx.iter_mut() {
+                    *pixel = 0;
+                }
+            }
+            (0x0, _) if opcode == 0x00EE => match self.stack.pop() {
+                Some(address) => self.pc = address,
+                None => return Err(FaultKind::StackUnderflow),
+            },
+            (0x1, _) => self.pc = nnn,
+            (0x2, _) => {
+                if self.stack.len() >= self.stack_depth {
+                    return Err(FaultKind::StackOverflow(self.stack_depth));
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            (0x3, _) => self.skip_if(self.v[x] == nn),
+            (0x4, _) => self.skip_if(self.v[x] != nn),
+            // the low nibble of 5XY0 and 9XY0 is ignored, like on the COSMAC VIP
+            (0x5, _) => self.skip_if(self.v[x] == self.v[y]),
+            (0x6, _) => self.v[x] = nn,
+            (0x7, _) => self.v[x] = ((self.v[x] as u16 + nn as u16) % 256) as u8,
+            (0x8, 0x0) => self.v[x] = self.v[y],
+            (0x8, 0x1..=0x3) => {
+                self.v[x] = match n {
+                    0x1 => self.v[x] | self.v[y],
+                    0x2 => self.v[x] & self.v[y],
+                    _ => self.v[x] ^ self.v[y],
+                };
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            (0x8, 0x4) => {
+                let sum = self.v[x] as u16 + self.v[y] as u16;
+                self.v[x] = (sum % 256) as u8;
+                self.v[0xF] = if sum > 255 { 1 } else { 0 };
+            }
+            (0x8, 0x5) => {
+                let (a, b) = (self.v[x], self.v[y]);
+                self.v[x] = ((a as u16 + 256 - b as u16) % 256) as u8;
+                self.v[0xF] = if a >= b { 1 } else { 0 };
+            }
+            (0x8, 0x7) => {
+                let (a, b) = (self.v[x], self.v[y]);
+                self.v[x] = ((b as u16 + 256 - a as u16) % 256) as u8;
+                self.v[0xF] = if b >= a { 1 } else { 0 };
+            }
+            (0x8, 0x6 | 0xE) => {
+                let value = if self.quirks.shift_uses_vy {
+                    self.v[y]
+                } else {
+                    self.v[x]
+                };
+                let (result, flag) = if n == 0x6 {
+                    (value / 2, value % 2)
+                } else {
+                    (((value as u16 * 2) % 256) as u8, value / 128)
+                };
+                self.v[x] = result;
+                self.v[0xF] = flag;
+            }
+            (0x9, _) => self.skip_if(self.v[x] != self.v[y]),
+            (0xA, _) => self.i = nnn,
+            (0xB, _) => {
+                let offset = if self.quirks.jump_uses_vx {
+                    self.v[x]
+                } else {
+                    self.v[0]
+                };
+                self.pc = nnn + offset as usize;
+            }
+            (0xC, _) => self.v[x] = self.rng.random::<u8>() & nn,
+            (0xD, _) => self.draw(x, y, n as usize)?,
+            (0xE, _) if nn == 0x9E => self.skip_if(self.keys[(self.v[x] & 0xF) as usize]),
+            (0xE, _) if nn == 0xA1 => self.skip_if(!self.keys[(self.v[x] & 0xF) as usize]),
+            (0xF, _) => match nn {
+                0x07 => self.v[x] = self.delay_timer,
+                0x0A => {
+                    let released = (0..16).find(|&key| self.prev_keys[key] && !self.keys[key]);
+                    match released {
+                        Some(key) => self.v[x] = key as u8,
+                        None => self.pc -= 2,
+                    }
+                }
+                0x15 => self.delay_timer = self.v[x],
+                0x18 => self.sound_timer = self.v[x],
+                0x1E => self.i += self.v[x] as usize,
+                0x29 => self.i = self.font_start + self.v[x] as usize * 5,
+                0x33 => {
+                    self.check_range(self.i, 3, true)?;
+                    self.write(self.i, self.v[x] / 100)?;
+                    self.write(self.i + 1, self.v[x] / 10 % 10)?;
+                    self.write(self.i + 2, self.v[x] % 10)?;
+                }
+                0x55 => {
+                    self.check_range(self.i, x + 1, true)?;
+                    for reg in 0..=x {
+                        self.write(self.i + reg, self.v[reg])?;
+                    }
+                    if self.quirks.memory_increment {
+                        self.i += x + 1;
+                    }
+                }
+                0x65 => {
+                    self.check_range(self.i, x + 1, false)?;
+                    for reg in 0..=x {
+                        self.v[reg] = self.read(self.i + reg)?;
+                    }
+                    if self.quirks.memory_increment {
+                        self.i += x + 1;
+                    }
+                }
+                0x75 if self.variant != Variant::Chip8 => {
+                    self.flags[..=x].copy_from_slice(&self.v[..=x])
+                }
+                0x85 if self.variant != Variant::Chip8 => {
+                    self.v[..=x].copy_from_slice(&self.flags[..=x])
+                }
+                _ => {}
+            },
+            _ => {} // unknown opcodes are skipped
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, x: usize, y: usize, height: usize) -> Result<(), FaultKind> {
+        self.check_range(self.i, height, false)?;
+
+        let start_x = self.v[x] as usize % SCREEN_WIDTH;
+        let start_y = self.v[y] as usize % SCREEN_HEIGHT;
+        self.v[0xF] = 0;
+
+        for row in 0..height {
+            let sprite_byte = self.read(self.i + row)?;
+
+            for col in 0..8 {
+                if sprite_byte & (0x80 >> col) == 0 {
+                    continue;
+                }
+
+                let (mut px, mut py) = (start_x + col, start_y + row);
+                if self.quirks.clip_sprites {
+                    if px >= SCREEN_WIDTH || py >= SCREEN_HEIGHT {
+                        continue;
+                    }
+                } else {
+                    px %= SCREEN_WIDTH;
+                    py %= SCREEN_HEIGHT;
+                }
+
+                let pixel = &mut self.gfx[py * SCREEN_WIDTH + px];
+                if *pixel == 1 {
+                    self.v[0xF] = 1;
+                }
+                *pixel ^= 1;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+// ROM containers. Plain binaries are loaded as they are. Programs transferred
+// from an HP-48 (CHIP-48/SCHIP) keep the calculator's binary header: the
+// 8 byte "HPHP48-x" transfer header followed by the 5 byte object prolog and
+// size, both of which are stripped.
+//
+// c8b files bundle the bytecode of one program for several platforms:
+//
+//     0  "CBF"
+//     3  version, 0
+//     4  offset of the property table (name, author, ..., not used here)
+//     6  bytecode table, 5 bytes per entry: platform, offset, size
+//        (big endian), a platform of 0 ends it
+//
+// The CHIP-8 entry is loaded, at the address the platform loads programs to.
+
+const HP48_MAGIC: &[u8] = b"HPHP48-";
+const HP48_HEADER_LEN: usize = 13;
+
+const C8B_MAGIC: &[u8] = b"CBF";
+const C8B_VERSION: u8 = 0;
+const C8B_TABLE_START: usize = 6;
+const C8B_ENTRY_LEN: usize = 5;
+
+// platform id and load address of the c8b programs that can run here
+const C8B_PLATFORMS: [(u8, usize); 1] = [(0x01, 0x200)];
+
+pub struct Program<'a> {
+    pub code: &'a [u8],
+    pub load_address: Option<usize>, // set by the container, None for plain binaries
+}
+
+pub fn unpack(data: &[u8]) -> Result<Program<'_>, String> {
+    if data.starts_with(HP48_MAGIC) && data.len() >= HP48_HEADER_LEN {
+        println!("Stripped HP-48 binary header");
+        return Ok(Program {
+            code: &data[HP48_HEADER_LEN..],
+            load_address: None,
+        });
+    }
+
+    if data.starts_with(C8B_MAGIC) {
+        return unpack_c8b(data);
+    }
+
+    Ok(Program {
+        code: data,
+        load_address: None,
+    })
+}
+
+fn unpack_c8b(data: &[u8]) -> Result<Program<'_>, String> {
+    match data.get(C8B_MAGIC.len()) {
+        Some(&C8B_VERSION) => {}
+        Some(version) => return Err(format!("c8b version {version} is not supported")),
+        None => return Err("c8b header is truncated".to_string()),
+    }
+
+    let mut platforms = Vec::new();
+    let mut entry_start = C8B_TABLE_START;
+    loop {
+        let truncated = || "c8b bytecode table is truncated".to_string();
+        let platform = *data.get(entry_start).ok_or_else(truncated)?;
+        if platform == 0 {
+            break;
+        }
+        let entry = data
+            .get(entry_start..entry_start + C8B_ENTRY_LEN)
+            .ok_or_else(truncated)?;
+
+        if let Some(&(_, load_address)) = C8B_PLATFORMS.iter().find(|(id, _)| *id == platform) {
+            let offset = u16::from_be_bytes([entry[1], entry[2]]) as usize;
+            let size = u16::from_be_bytes([entry[3], entry[4]]) as usize;
+            let Some(code) = data.get(offset..offset + size) else {
+                return Err(format!(
+                    "c8b bytecode for platform {platform:#04X} is truncated"
+                ));
+            };
+            println!("Loaded platform {platform:#04X} bytecode from c8b file");
+            return Ok(Program {
+                code,
+                load_address: Some(load_address),
+            });
+        }
+
+        platforms.push(format!("{platform:#04X}"));
+        entry_start += C8B_ENTRY_LEN;
+    }
+
+    Err(format!(
+        "c8b file has no CHIP-8 bytecode, only platforms {}",
+        platforms.join(" ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_and_hp48_binaries() {
+        let program = unpack(&[0x00, 0xE0]).unwrap();
+        assert_eq!(program.code, [0x00, 0xE0]);
+        assert_eq!(program.load_address, None);
+
+        let mut hp48 = b"HPHP48-A".to_vec();
+        hp48.extend([0x2B, 0x2C, 0x3F, 0x10, 0x00, 0x00, 0xE0]);
+        assert_eq!(unpack(&hp48).unwrap().code, [0x00, 0xE0]);
+    }
+
+    #[test]
+    fn c8b_picks_the_chip8_bytecode() {
+        // header, SCHIP entry, CHIP-8 entry, end of table, two programs
+        let mut c8b = b"CBF\x00\x00\x00".to_vec();
+        c8b.extend([0x11, 0x00, 0x11, 0x00, 0x02]);
+        c8b.extend([0x01, 0x00, 0x13, 0x00, 0x04]);
+        c8b.push(0x00);
+        c8b.extend([0x00, 0xFF, 0x00, 0xE0, 0x12, 0x00]);
+
+        let program = unpack(&c8b).unwrap();
+        assert_eq!(program.code, [0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(program.load_address, Some(0x200));
+    }
+
+    #[test]
+    fn c8b_errors() {
+        let Err(err) = unpack(b"CBF\x00\x00\x00\x11\x00\x0C\x00\x02\x00\x00\xFF") else {
+            panic!("file without CHIP-8 bytecode was accepted");
+        };
+        assert_eq!(err, "c8b file has no CHIP-8 bytecode, only platforms 0x11");
+
+        let Err(err) = unpack(b"CBF\x00\x00\x00\x01\x00\x0C\x00\x08\x00\x00\xE0") else {
+            panic!("truncated bytecode was accepted");
+        };
+        assert_eq!(err, "c8b bytecode for platform 0x01 is truncated");
+
+        let Err(err) = unpack(b"CBF\x01") else {
+            panic!("unknown version was accepted");
+        };
+        assert_eq!(err, "c8b version 1 is not supported");
+    }
+}
@@ -0,0 +1,249 @@
+// Per-game profiles keyed by ROM SHA-1. The built-in entries live in romdb.cfg,
+// user entries with the same syntax are read from rusty8.cfg in the config
+// directory and take precedence.
+
+use crate::cheats::parse_number;
+use crate::keymap::{DEFAULT_BINDINGS, KeyBindings, parse_binding};
+use crate::paths::config_dir;
+use crate::quirks::{Quirks, Variant};
+use crate::{FONTSET_START, PROGRAM_START};
+use std::collections::HashMap;
+use std::fs;
+
+const BUILTIN_DB: &str = include_str!("romdb.cfg");
+
+#[derive(Clone)]
+pub struct Profile {
+    pub name: Option<String>,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub instr_per_frame: usize,
+    pub key_bindings: KeyBindings,
+    pub program_start: usize,
+    pub font_start: usize,
+}
+
+impl Profile {
+    // resets quirks and speed to the variant's defaults
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.quirks = variant.default_quirks();
+        self.instr_per_frame = variant.default_instr_per_frame();
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            name: None,
+            variant: Variant::Chip8,
+            quirks: Variant::Chip8.default_quirks(),
+            instr_per_frame: Variant::Chip8.default_instr_per_frame(),
+            key_bindings: DEFAULT_BINDINGS.to_vec(),
+            program_start: PROGRAM_START,
+            font_start: FONTSET_START,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RomDb {
+    entries: HashMap<String, Profile>,
+}
+
+impl RomDb {
+    pub fn load() -> Self {
+        let mut db = RomDb::default();
+        for warning in db.parse(BUILTIN_DB) {
+            eprintln!("built-in database, {warning}");
+        }
+
+        if let Some(path) = config_dir().map(|dir| dir.join("rusty8.cfg"))
+            && let Ok(text) = fs::read_to_string(&path)
+        {
+            for warning in db.parse(&text) {
+                eprintln!("{}, {warning}", path.display());
+            }
+        }
+
+        db
+    }
+
+    pub fn take(&mut self, rom_hash: &str) -> Option<Profile> {
+        self.entries.remove(rom_hash)
+    }
+
+    // returns a warning per line that was skipped or only partly understood
+    fn parse(&mut self, text: &str) -> Vec<String> {
+        let mut current: Option<String> = None;
+        let mut warnings = Vec::new();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut warn = |msg: &str| warnings.push(format!("line {}: {msg}", line_no + 1));
+
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = match header.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["rom", hash] => {
+                        // a later entry for the same ROM replaces the earlier one
+                        let hash = hash.to_ascii_lowercase();
+                        self.entries.insert(hash.clone(), Profile::default());
+                        Some(hash)
+                    }
+                    _ => {
+                        warn("unknown section, expected [rom <sha1>]");
+                        None
+                    }
+                };
+                continue;
+            }
+
+            let Some(profile) = current.as_ref().and_then(|hash| self.entries.get_mut(hash)) else {
+                warn("setting outside of a [rom <sha1>] section");
+                continue;
+            };
+
+            let Some((key, value)) = line.split_once('=') else {
+                warn("expected <setting> = <value>");
+                continue;
+            };
+
+            match key.trim() {
+                "name" => profile.name = Some(value.trim().to_string()),
+                "variant" => match Variant::from_name(value.trim()) {
+                    Some(variant) => profile.set_variant(variant),
+                    None => warn("unknown variant"),
+                },
+                "quirks" => {
+                    for token in value.split_whitespace() {
+                        if let Err(message) = profile.quirks.apply(token) {
+                            warn(&message);
+                        }
+                    }
+                }
+                "tickrate" => match value.trim().parse() {
+                    Ok(rate) if rate > 0 => profile.instr_per_frame = rate,
+                    _ => warn("tickrate must be a positive number"),
+                },
+                "keys" => {
+                    for token in value.split_whitespace() {
+                        match parse_binding(token) {
+                            Some(binding) => profile.key_bindings.push(binding),
+                            None => warn(&format!("invalid key binding '{token}'")),
+                        }
+                    }
+                }
+                "start" => match parse_number(value.trim()) {
+                    Some(address) => profile.program_start = address,
+                    None => warn("start must be an address like 0x600"),
+                },
+                "font" => match parse_number(value.trim()) {
+                    Some(address) => profile.font_start = address,
+                    None => warn("font must be an address like 0x50"),
+                },
+                other => warn(&format!("unknown setting '{other}'")),
+            }
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::HostKey;
+
+    const HASH: &str = "3a1c0f5e2b4d6a7c8e9f0a1b2c3d4e5f6a7b8c9d";
+
+    fn parse(text: &str) -> (RomDb, Vec<String>) {
+        let mut db = RomDb::default();
+        let warnings = db.parse(text);
+        (db, warnings)
+    }
+
+    #[test]
+    fn builtin_database_parses_cleanly() {
+        let (db, warnings) = parse(BUILTIN_DB);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert!(!db.entries.is_empty());
+    }
+
+    #[test]
+    fn parses_a_section() {
+        let text = format!(
+            "# comment\n[rom {}]\nname = Some Game\nvariant = schip\nquirks = -clip_sprites +jump_uses_vx\n\
+             keys = left:7 right:8\nstart = 0x600\nfont = 0x0\n",
+            HASH.to_ascii_uppercase()
+        );
+        let (mut db, warnings) = parse(&text);
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        let profile = db.take(HASH).unwrap();
+        assert_eq!(profile.name.as_deref(), Some("Some Game"));
+        assert!(profile.variant == Variant::SuperChip);
+        assert!(!profile.quirks.clip_sprites);
+        assert!(profile.quirks.jump_uses_vx);
+        assert_eq!(profile.program_start, 0x600);
+        assert_eq!(profile.font_start, 0);
+        assert_eq!(profile.key_bindings.len(), DEFAULT_BINDINGS.len() + 2);
+        assert_eq!(profile.key_bindings.last(), Some(&(HostKey::Right, 8)));
+        assert!(db.take(HASH).is_none());
+    }
+
+    #[test]
+    fn variant_resets_tickrate_and_quirks() {
+        let text = format!("[rom {HASH}]\ntickrate = 30\nquirks = -vf_reset\nvariant = xochip\n");
+        let profile = parse(&text).0.take(HASH).unwrap();
+        assert_eq!(
+            profile.instr_per_frame,
+            Variant::XoChip.default_instr_per_frame()
+        );
+        assert!(profile.quirks == Variant::XoChip.default_quirks());
+
+        let text = format!("[rom {HASH}]\nvariant = xochip\ntickrate = 30\n");
+        assert_eq!(parse(&text).0.take(HASH).unwrap().instr_per_frame, 30);
+    }
+
+    #[test]
+    fn later_entries_replace_earlier_ones() {
+        let mut db = RomDb::default();
+        db.parse(&format!("[rom {HASH}]\nname = Built-in\ntickrate = 30\n"));
+        db.parse(&format!("[rom {HASH}]\nname = User\n"));
+
+        let profile = db.take(HASH).unwrap();
+        assert_eq!(profile.name.as_deref(), Some("User"));
+        assert_eq!(
+            profile.instr_per_frame,
+            Variant::Chip8.default_instr_per_frame()
+        );
+    }
+
+    #[test]
+    fn bad_lines_produce_warnings() {
+        let text = format!(
+            "tickrate = 20\n[game {HASH}]\n[rom {HASH}]\nname\nvariant = gameboy\nquirks = +turbo\n\
+             tickrate = 0\nkeys = left:x\nstart = here\ncolour = red\ntickrate = 25\n"
+        );
+        let (mut db, warnings) = parse(&text);
+
+        let lines: Vec<&str> = warnings
+            .iter()
+            .map(|warning| warning.split(':').next().unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "line 1", "line 2", "line 4", "line 5", "line 6", "line 7", "line 8", "line 9",
+                "line 10"
+            ]
+        );
+
+        // the valid settings of the section still apply
+        assert_eq!(db.take(HASH).unwrap().instr_per_frame, 25);
+    }
+}
@@ -0,0 +1,288 @@
+// Terminal frontend, draws two CHIP-8 rows per text line with half-block
+// characters. Usable over SSH or without a display server.
+//
+// Most terminals only report key presses, not releases. Where the terminal
+// supports the kitty keyboard protocol we get real release events, otherwise a
+// key counts as held for KEY_HOLD after its last press or auto-repeat. The first
+// repeat comes after the terminal's repeat delay, usually 250-500 ms, a shorter
+// hold time would drop held keys in between.
+
+use crate::audio::Audio;
+use crate::frontend::Frontend;
+use crate::hooks::{Display, Hooks, Sound};
+use crate::keymap::{HostKey, KeyBindings};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+const KEY_HOLD: Duration = Duration::from_millis(500);
+
+pub struct TuiFrontend {
+    stdout: Stdout,
+    key_bindings: KeyBindings,
+    pressed: Vec<Option<Instant>>, // per binding, time of the last press
+    release_events: bool,
+    hotkeys: [bool; 12],
+    open: bool,
+    last_gfx: Vec<u8>,
+    last_pixels: Vec<u32>,
+    audio: Option<Audio>,
+}
+
+impl TuiFrontend {
+    pub fn new(key_bindings: KeyBindings) -> io::Result<Self> {
+        let mut stdout = io::stdout();
+
+        // before raw mode, audio libraries may print warnings
+        let audio = Audio::new();
+
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+
+        let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if release_events {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(TuiFrontend {
+            stdout,
+            pressed: vec![None; key_bindings.len()],
+            key_bindings,
+            release_events,
+            hotkeys: [false; 12],
+            open: true,
+            last_gfx: Vec::new(),
+            last_pixels: Vec::new(),
+            audio,
+        })
+    }
+
+    fn handle_key(&mut self, event: KeyEvent) {
+        let is_press = event.kind != KeyEventKind::Release;
+
+        // quit keys are never passed on, Ctrl+C would press the binding for 'c'
+        match event.code {
+            KeyCode::Esc => {
+                self.open = false;
+                return;
+            }
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.open = false;
+                return;
+            }
+            KeyCode::F(n @ 1..=12) if event.kind == KeyEventKind::Press => {
+                self.hotkeys[n as usize - 1] = true
+            }
+            _ => {}
+        }
+
+        let Some(key) = host_key(event.code) else {
+            return;
+        };
+
+        let now = Instant::now();
+        for (idx, &(bound, _)) in self.key_bindings.iter().enumerate() {
+            if bound == key {
+                self.pressed[idx] = is_press.then_some(now);
+            }
+        }
+    }
+
+    fn draw(&mut self, gfx: &[u8]) {
+        // only redraw on change, keeps the output small over slow connections
+        if gfx == self.last_gfx.as_slice() {
+            return;
+        }
+        self.last_gfx = gfx.to_vec();
+
+        queue!(
+            self.stdout,
+            SetForegroundColor(Color::Rgb {
+                r: 0xFF,
+                g: 0xA5,
+                b: 0x00
+            })
+        )
+        .unwrap();
+
+        for row in 0..SCREEN_HEIGHT / 2 {
+            let top = &gfx[row * 2 * SCREEN_WIDTH..][..SCREEN_WIDTH];
+            let bottom = &gfx[(row * 2 + 1) * SCREEN_WIDTH..][..SCREEN_WIDTH];
+
+            let line: String = top
+                .iter()
+                .zip(bottom)
+                .map(|pixels| match pixels {
+                    (0, 0) => ' ',
+                    (_, 0) => '▀',
+                    (0, _) => '▄',
+                    _ => '█',
+                })
+                .collect();
+
+            queue!(self.stdout, cursor::MoveTo(0, row as u16), Print(line)).unwrap();
+        }
+
+        self.stdout.flush().unwrap();
+    }
+
+    // MegaChip is too large for most terminals at full size, only every second
+    // column and row is shown, two rows per text line in fore- and background color
+    fn draw_color(&mut self, pixels: &[u32], width: usize, height: usize) {
+        if pixels == self.last_pixels.as_slice() {
+            return;
+        }
+        self.last_pixels = pixels.to_vec();
+        self.last_gfx.clear();
+
+        let rgb = |color: u32| Color::Rgb {
+            r: (color >> 16) as u8,
+            g: (color >> 8) as u8,
+            b: color as u8,
+        };
+
+        // at most 128 columns and 48 rows of half blocks, MegaChip is downsampled
+        let step_x = width.div_ceil(128);
+        let step_y = height.div_ceil(96);
+
+        for row in 0..height / (step_y * 2) {
+            queue!(self.stdout, cursor::MoveTo(0, row as u16)).unwrap();
+
+            for col in 0..width / step_x {
+                let top = pixels[row * 2 * step_y * width + col * step_x];
+                let bottom = pixels[(row * 2 + 1) * step_y * width + col * step_x];
+                queue!(
+                    self.stdout,
+                    SetForegroundColor(rgb(top)),
+                    SetBackgroundColor(rgb(bottom)),
+                    Print('▀')
+                )
+                .unwrap();
+            }
+        }
+
+        queue!(self.stdout, ResetColor).unwrap();
+        self.stdout.flush().unwrap();
+    }
+}
+
+impl Drop for TuiFrontend {
+    fn drop(&mut self) {
+        if self.release_events {
+            let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.stdout, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Hooks for TuiFrontend {
+    fn on_draw(&mut self, display: Display, _keys: &[bool; 16]) {
+        match display {
+            Display::Mono(gfx) => self.draw(gfx),
+            Display::Color {
+                pixels,
+                width,
+                height,
+            } => self.draw_color(pixels, width, height),
+        }
+    }
+
+    fn on_sound_start(&mut self, sound: Sound) {
+        if let Some(audio) = &self.audio {
+            audio.play(sound);
+        }
+    }
+
+    fn on_sound_stop(&mut self) {
+        if let Some(audio) = &self.audio {
+            audio.stop();
+        }
+    }
+
+    // printing would scroll the alternate screen, use the line below the status
+    fn on_unknown_opcode(&mut self, pc: usize, opcode: u16) {
+        queue!(
+            self.stdout,
+            SetForegroundColor(Color::Reset),
+            cursor::MoveTo(0, (SCREEN_HEIGHT / 2 + 2) as u16),
+            terminal::Clear(ClearType::CurrentLine),
+            Print(format!("Unknown opcode {opcode:#06X} at {pc:#05X}")),
+        )
+        .unwrap();
+        self.stdout.flush().unwrap();
+    }
+}
+
+impl Frontend for TuiFrontend {
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn read_keys(&mut self, keys: &mut [bool; 16]) {
+        self.hotkeys = [false; 12];
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            if let Ok(Event::Key(event)) = event::read() {
+                self.handle_key(event);
+            }
+        }
+
+        let now = Instant::now();
+        keys.fill(false);
+
+        for (idx, &(_, chip_key)) in self.key_bindings.iter().enumerate() {
+            if let Some(since) = self.pressed[idx] {
+                if self.release_events || now.duration_since(since) < KEY_HOLD {
+                    keys[chip_key] = true;
+                } else {
+                    self.pressed[idx] = None;
+                }
+            }
+        }
+    }
+
+    fn cheat_hotkey_pressed(&self, idx: usize) -> bool {
+        self.hotkeys[idx]
+    }
+
+    fn set_status(&mut self, status: &str) {
+        queue!(
+            self.stdout,
+            SetForegroundColor(Color::Reset),
+            cursor::MoveTo(0, (SCREEN_HEIGHT / 2) as u16),
+            terminal::Clear(ClearType::CurrentLine),
+            Print(status),
+            cursor::MoveTo(0, (SCREEN_HEIGHT / 2 + 1) as u16),
+            terminal::Clear(ClearType::CurrentLine),
+            Print("Esc: quit | F1-F12: cheats"),
+        )
+        .unwrap();
+        self.stdout.flush().unwrap();
+    }
+}
+
+fn host_key(code: KeyCode) -> Option<HostKey> {
+    let key = match code {
+        KeyCode::Up => HostKey::Up,
+        KeyCode::Down => HostKey::Down,
+        KeyCode::Left => HostKey::Left,
+        KeyCode::Right => HostKey::Right,
+        KeyCode::Enter => HostKey::Enter,
+        KeyCode::Char(' ') => HostKey::Space,
+        KeyCode::Char(c) if c.is_ascii_alphanumeric() => HostKey::Char(c.to_ascii_lowercase()),
+        _ => return None,
+    };
+
+    Some(key)
+}
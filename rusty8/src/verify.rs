@@ -0,0 +1,204 @@
+// Differential testing against the plain interpreter in reference.rs.
+//
+// --verify runs one program (a ROM or a fuzzer-generated input, e.g. from AFL)
+// on both implementations with the same RNG seed and key presses and compares
+// the complete machine state after every instruction. --fuzz does the same for
+// randomly generated instruction streams and saves the first diverging one so
+// it can be replayed with --verify.
+//
+// Both runs use every bounds check, a panic in the interpreter is a finding too.
+
+use crate::diff::diff;
+use crate::flags::FLAG_COUNT;
+use crate::hooks::NoHooks;
+use crate::quirks::Variant;
+use crate::reference::Reference;
+use crate::romdb::Profile;
+use crate::{Chip8, Limits, PROGRAM_START};
+use rand::prelude::*;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+
+const VERIFY_FRAMES: usize = 600;
+const VERIFY_SEED: u64 = 0;
+const FUZZ_FRAMES: usize = 60;
+
+enum Outcome {
+    Completed,
+    Faulted(String),
+    Diverged(String),
+}
+
+// exit code for --verify: 0 if both implementations agree, 1 otherwise
+pub fn run_verify(program: &[u8], profile: &Profile, limits: &Limits) -> i32 {
+    if profile.variant == Variant::MegaChip {
+        eprintln!("The reference implementation does not cover MegaChip");
+        return 1;
+    }
+
+    let (outcome, steps) = verify(program, profile, limits, VERIFY_FRAMES);
+
+    match outcome {
+        Outcome::Completed => {
+            println!("Verified {steps} instructions, no divergence");
+            0
+        }
+        Outcome::Faulted(fault) => {
+            println!("Verified {steps} instructions, both stopped at {fault}");
+            0
+        }
+        Outcome::Diverged(report) => {
+            println!("Divergence after {steps} instructions: {report}");
+            1
+        }
+    }
+}
+
+// exit code for --fuzz: 0 if all generated programs verified, 1 otherwise
+pub fn run_fuzz(iterations: usize, stack_depth: usize) -> i32 {
+    // arithmetic bugs only panic with overflow checks, which release builds drop
+    if !cfg!(debug_assertions) {
+        eprintln!("--fuzz needs overflow checks, build with --profile fuzz or without --release");
+        return 1;
+    }
+
+    let mut rng = StdRng::from_os_rng();
+    let mut total_steps = 0;
+
+    for iteration in 0..iterations {
+        // fill everything a 12 bit jump can reach, random jumps into zeroed
+        // memory would otherwise only exercise the unknown opcode path
+        let program: Vec<u8> = (PROGRAM_START..0x1000)
+            .step_by(2)
+            .flat_map(|_| {
+                // plain random words rarely hit 00E0/00EE, mix them in, and
+                // keep jumps and calls out of the zeroed memory below the program
+                let opcode: u16 = match rng.random_range(0..32) {
+                    0 => 0x00E0,
+                    1 => 0x00EE,
+                    _ => match rng.random::<u16>() {
+                        jump @ (0x1000..=0x2FFF | 0xB000..=0xBFFF) => {
+                            jump & 0xF000 | rng.random_range(PROGRAM_START as u16..0x1000) & !1
+                        }
+                        opcode => opcode,
+                    },
+                };
+                opcode.to_be_bytes()
+            })
+            .collect();
+
+        let variant = [Variant::Chip8, Variant::SuperChip, Variant::XoChip][rng.random_range(0..3)];
+        let mut profile = Profile::default();
+        profile.set_variant(variant);
+        let limits = Limits {
+            stack_depth,
+            strict_memory: rng.random(),
+        };
+
+        // a panic is as much a finding as a divergence, keep the program for replay
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            verify(&program, &profile, &limits, FUZZ_FRAMES)
+        }));
+        let report = match result {
+            Ok((Outcome::Diverged(report), steps)) => {
+                format!("Divergence after {steps} instructions: {report}")
+            }
+            Ok((_, steps)) => {
+                total_steps += steps;
+                continue;
+            }
+            Err(_) => "Panicked while running the program".to_string(),
+        };
+
+        let path = format!("fuzz-failure-{iteration}.ch8");
+        let saved = fs::write(&path, &program).is_ok();
+
+        println!("{report}");
+        if saved {
+            println!(
+                "Replay with: --verify --no-db --variant {}{} {path}",
+                variant.name(),
+                if limits.strict_memory {
+                    " --strict"
+                } else {
+                    ""
+                }
+            );
+        }
+        return 1;
+    }
+
+    println!("Fuzzed {iterations} programs, {total_steps} instructions, no divergence");
+    0
+}
+
+fn verify(program: &[u8], profile: &Profile, limits: &Limits, frames: usize) -> (Outcome, usize) {
+    let mut chip = Chip8::new(program, profile, [0; FLAG_COUNT], limits);
+    chip.rng = StdRng::seed_from_u64(VERIFY_SEED);
+
+    let mut reference = Reference::new(program, profile, limits, VERIFY_SEED);
+    let mut input_rng = StdRng::seed_from_u64(VERIFY_SEED + 1);
+    let mut steps = 0;
+
+    let differences = diff(&chip.state(), &reference.state());
+    if !differences.is_empty() {
+        let report = format!("initial state differs, {}", differences.join(", "));
+        return (Outcome::Diverged(report), 0);
+    }
+
+    for _ in 0..frames {
+        let mask: u16 = input_rng.random();
+        let keys: [bool; 16] = std::array::from_fn(|key| mask & (1 << key) != 0);
+
+        chip.prev_keys = chip.keys;
+        chip.keys = keys;
+        chip.update_timers();
+
+        reference.prev_keys = reference.keys;
+        reference.keys = keys;
+        reference.update_timers();
+
+        for _ in 0..profile.instr_per_frame {
+            let pc = chip.pc;
+            let opcode = chip
+                .memory
+                .get(pc..pc + 2)
+                .map_or(0, |op| u16::from_be_bytes([op[0], op[1]]));
+
+            let result = chip.step(&mut NoHooks);
+            let reference_result = reference.step();
+            steps += 1;
+
+            match (result, reference_result) {
+                (Ok(()), Ok(())) => {}
+                (Err(fault), Err((ref_pc, ref_kind)))
+                    if fault.pc == ref_pc && fault.kind == ref_kind =>
+                {
+                    return (Outcome::Faulted(fault.to_string()), steps);
+                }
+                (result, reference_result) => {
+                    let describe =
+                        |fault: Option<String>| fault.unwrap_or_else(|| "ok".to_string());
+                    let report = format!(
+                        "{opcode:#06X} at {pc:#05X}: interpreter {}, reference {}",
+                        describe(result.err().map(|fault| fault.to_string())),
+                        describe(
+                            reference_result
+                                .err()
+                                .map(|(pc, kind)| format!("PC {pc:#05X}: {kind:?}"))
+                        ),
+                    );
+                    return (Outcome::Diverged(report), steps);
+                }
+            }
+
+            let differences = diff(&chip.state(), &reference.state());
+            if !differences.is_empty() {
+                let report = format!("{opcode:#06X} at {pc:#05X}: {}", differences.join(", "));
+                return (Outcome::Diverged(report), steps);
+            }
+        }
+    }
+
+    (Outcome::Completed, steps)
+}
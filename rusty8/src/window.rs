@@ -0,0 +1,224 @@
+use crate::audio::Audio;
+use crate::frontend::Frontend;
+use crate::hooks::{Display, Hooks, Sound};
+use crate::keymap::{HostKey, KeyBindings};
+use crate::overlay::draw_keypad;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+
+const CHEAT_HOTKEYS: [Key; 12] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+pub struct WindowFrontend {
+    window: Window,
+    width: usize,  // window size in pixels
+    height: usize, // window size in pixels
+    screen_buffer: [u32; SCREEN_WIDTH * SCREEN_HEIGHT],
+    key_bindings: KeyBindings,
+    show_keypad: bool,
+    overlay_buffer: Vec<u32>, // upscaled screen_buffer with the keypad on top
+    audio: Option<Audio>,
+}
+
+impl WindowFrontend {
+    // display_width/height is what the window shows without stretching: 64x32,
+    // 256x192 for MegaChip, two screens next to each other for --compare
+    pub fn new(key_bindings: KeyBindings, display_width: usize, display_height: usize) -> Self {
+        // window pixels per display pixel, keeps the window about 1024 pixels wide
+        let (scale, minifb_scale) = match 1024 / display_width {
+            16.. => (16, Scale::X16),
+            8.. => (8, Scale::X8),
+            4.. => (4, Scale::X4),
+            2.. => (2, Scale::X2),
+            _ => (1, Scale::X1),
+        };
+        let width = display_width * scale;
+        let height = display_height * scale;
+
+        WindowFrontend {
+            window: Window::new(
+                "Rusty8",
+                display_width,
+                display_height,
+                WindowOptions {
+                    scale: minifb_scale,
+                    ..WindowOptions::default()
+                },
+            )
+            .unwrap(),
+            width,
+            height,
+            screen_buffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            key_bindings,
+            show_keypad: false,
+            overlay_buffer: vec![0; width * height],
+            audio: Audio::new(),
+        }
+    }
+
+    fn draw(&mut self, gfx: &[u8], keys: &[bool; 16]) {
+        for (i, &pixel) in gfx.iter().enumerate() {
+            self.screen_buffer[i] = if pixel == 0 { 0x000000 } else { 0xFFA500 };
+        }
+
+        let screen_buffer = self.screen_buffer;
+        self.draw_color(&screen_buffer, SCREEN_WIDTH, SCREEN_HEIGHT, keys);
+    }
+
+    fn draw_color(&mut self, pixels: &[u32], width: usize, height: usize, keys: &[bool; 16]) {
+        if !self.show_keypad {
+            // minifb stretches the buffer to the window
+            self.window
+                .update_with_buffer(pixels, width, height)
+                .unwrap();
+            return;
+        }
+
+        let overlay_width = self.width;
+        let overlay_height = self.height;
+
+        for (y, row) in self
+            .overlay_buffer
+            .chunks_exact_mut(overlay_width)
+            .enumerate()
+        {
+            let src = &pixels[(y * height / overlay_height) * width..][..width];
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = src[x * width / overlay_width];
+            }
+        }
+
+        draw_keypad(
+            &mut self.overlay_buffer,
+            overlay_width,
+            overlay_height,
+            &self.key_bindings,
+            keys,
+        );
+
+        self.window
+            .update_with_buffer(&self.overlay_buffer, overlay_width, overlay_height)
+            .unwrap();
+    }
+}
+
+impl Hooks for WindowFrontend {
+    fn on_draw(&mut self, display: Display, keys: &[bool; 16]) {
+        match display {
+            Display::Mono(gfx) => self.draw(gfx, keys),
+            Display::Color {
+                pixels,
+                width,
+                height,
+            } => self.draw_color(pixels, width, height, keys),
+        }
+    }
+
+    fn on_sound_start(&mut self, sound: Sound) {
+        if let Some(audio) = &self.audio {
+            audio.play(sound);
+        }
+    }
+
+    fn on_sound_stop(&mut self) {
+        if let Some(audio) = &self.audio {
+            audio.stop();
+        }
+    }
+
+    fn on_unknown_opcode(&mut self, _pc: usize, opcode: u16) {
+        println!("Unknown opcode: {:#04X}", opcode);
+    }
+}
+
+impl Frontend for WindowFrontend {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn read_keys(&mut self, keys: &mut [bool; 16]) {
+        if self.window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+            self.show_keypad = !self.show_keypad;
+        }
+
+        keys.fill(false);
+
+        for &(key, chip_key) in &self.key_bindings {
+            keys[chip_key] |= minifb_key(key).is_some_and(|key| self.window.is_key_down(key));
+        }
+    }
+
+    fn cheat_hotkey_pressed(&self, idx: usize) -> bool {
+        self.window
+            .is_key_pressed(CHEAT_HOTKEYS[idx], KeyRepeat::No)
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.window.set_title(status);
+        println!("{status}");
+    }
+}
+
+fn minifb_key(key: HostKey) -> Option<Key> {
+    let key = match key {
+        HostKey::Up => Key::Up,
+        HostKey::Down => Key::Down,
+        HostKey::Left => Key::Left,
+        HostKey::Right => Key::Right,
+        HostKey::Space => Key::Space,
+        HostKey::Enter => Key::Enter,
+        HostKey::Char(c) => match c {
+            '0' => Key::Key0,
+            '1' => Key::Key1,
+            '2' => Key::Key2,
+            '3' => Key::Key3,
+            '4' => Key::Key4,
+            '5' => Key::Key5,
+            '6' => Key::Key6,
+            '7' => Key::Key7,
+            '8' => Key::Key8,
+            '9' => Key::Key9,
+            'a' => Key::A,
+            'b' => Key::B,
+            'c' => Key::C,
+            'd' => Key::D,
+            'e' => Key::E,
+            'f' => Key::F,
+            'g' => Key::G,
+            'h' => Key::H,
+            'i' => Key::I,
+            'j' => Key::J,
+            'k' => Key::K,
+            'l' => Key::L,
+            'm' => Key::M,
+            'n' => Key::N,
+            'o' => Key::O,
+            'p' => Key::P,
+            'q' => Key::Q,
+            'r' => Key::R,
+            's' => Key::S,
+            't' => Key::T,
+            'u' => Key::U,
+            'v' => Key::V,
+            'w' => Key::W,
+            'x' => Key::X,
+            'y' => Key::Y,
+            'z' => Key::Z,
+            _ => return None,
+        },
+    };
+
+    Some(key)
+}